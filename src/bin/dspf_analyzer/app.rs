@@ -5,16 +5,16 @@ use std::{
     thread::{self, JoinHandle},
 };
 
-use dspf_parse::dspf::LoadStatus;
 use dspf_parse::dspf::{netlist::AggrNet, Dspf};
+use dspf_parse::dspf::{CacheMode, LoadOptions, LoadStatus};
 
 use color_eyre::Result;
 
 use crate::{
     tui::Tui,
     windows::{
-        main_menu::MainMenuUI, net_cap_main::NetCapMainUI, res_main::ResMainUI, ProgressUI, Render,
-        Window,
+        delay_main::DelayMainUI, main_menu::MainMenuUI, net_cap_main::NetCapMainUI,
+        res_main::ResMainUI, ProgressUI, Render, Window,
     },
 };
 
@@ -33,12 +33,14 @@ pub enum Action {
 pub enum MainMenuOption {
     CapAnalysis,
     ResAnalysis,
+    DelayAnalysis,
     Quit,
 }
 
-static MENU_OPTIONS: [MainMenuOption; 3] = [
+static MENU_OPTIONS: [MainMenuOption; 4] = [
     MainMenuOption::CapAnalysis,
     MainMenuOption::ResAnalysis,
+    MainMenuOption::DelayAnalysis,
     MainMenuOption::Quit,
 ];
 
@@ -47,6 +49,7 @@ impl fmt::Display for MainMenuOption {
         match self {
             MainMenuOption::CapAnalysis => write!(f, " Report capacitance for net..."),
             MainMenuOption::ResAnalysis => write!(f, " Path resistance [experimental]..."),
+            MainMenuOption::DelayAnalysis => write!(f, " RC delay estimate for net..."),
             MainMenuOption::Quit => write!(f, " Quit"),
         }
     }
@@ -81,7 +84,14 @@ impl App {
 
         let p = path.to_owned();
         app.joinhandle = Some(thread::spawn(move || -> Result<Dspf> {
-            Dspf::load(&p, Some(Arc::clone(&status)))
+            // skip over lines we don't understand, they are listed in the main menu.
+            // The parsed netlist is cached next to the file to make re-opening it fast.
+            let options = LoadOptions {
+                strict: false,
+                cache: CacheMode::NextToSource,
+                ..LoadOptions::default()
+            };
+            Dspf::load(&p, &options, Some(Arc::clone(&status)))
         }));
 
         let x = app.main_loop();
@@ -140,6 +150,9 @@ impl App {
                 MainMenuOption::ResAnalysis => {
                     self.current_ui = Window::Res(ResMainUI::new(dspf.clone()));
                 }
+                MainMenuOption::DelayAnalysis => {
+                    self.current_ui = Window::Delay(DelayMainUI::new(dspf.clone()));
+                }
                 MainMenuOption::Quit => {
                     self.quit();
                 }
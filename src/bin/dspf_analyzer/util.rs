@@ -48,6 +48,11 @@ pub fn eng_format_res(value: f64, value_for_scale: f64) -> String {
     format!("{}Ω", s)
 }
 
+pub fn eng_format_time(value: f64, value_for_scale: f64) -> String {
+    let s = eng_format_scale(value, value_for_scale);
+    format!("{}s", s)
+}
+
 // https://docs.rs/ratatui/latest/src/ratatui/widgets/gauge.rs.html#221
 fn get_unicode_block<'a>(frac: f64) -> &'a str {
     match (frac * 8.0).round() as u16 {
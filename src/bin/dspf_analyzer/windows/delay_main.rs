@@ -0,0 +1,182 @@
+use crate::{app::Action, event::Event};
+use crossterm::event::KeyCode;
+use dspf_parse::dspf::netlist::NetInfo;
+use dspf_parse::dspf::Dspf;
+use ratatui::prelude::*;
+use ratatui::Frame;
+use std::rc::Rc;
+
+use super::delay_result::DelayResultWidget;
+use super::multi_node_selection::MultiNodeSelectionWidget;
+use super::net_selection::NetSelectionWidget;
+use super::status_bar::StatusBar;
+use super::Render;
+
+/// Number of moments computed for the delay metrics (only m1 and m2 are displayed)
+const NUM_MOMENTS: usize = 3;
+
+#[derive(PartialEq)]
+enum FocusUI {
+    Driver,
+    Result,
+}
+
+pub struct DelayMainUI {
+    dspf: Rc<Dspf>,
+    selected_net: Option<String>,
+    driver: Option<String>,
+    miller_factor: f64,
+    net_selection_widget: NetSelectionWidget,
+    driver_selection_widget: MultiNodeSelectionWidget,
+    result_widget: DelayResultWidget,
+    focus: FocusUI,
+}
+
+impl DelayMainUI {
+    pub fn new(dspf: Rc<Dspf>) -> Self {
+        let nets: Vec<NetInfo> = dspf
+            .netlist
+            .all_nets
+            .iter()
+            .map(|net| net.info.clone())
+            .collect();
+
+        Self {
+            dspf,
+            selected_net: None,
+            driver: None,
+            miller_factor: 1.0,
+            net_selection_widget: NetSelectionWidget::new(nets, "Select net:", true),
+            driver_selection_widget: MultiNodeSelectionWidget::default(),
+            result_widget: DelayResultWidget::default(),
+            focus: FocusUI::Driver,
+        }
+    }
+
+    fn set_focus(&mut self, focus: FocusUI) {
+        self.focus = focus;
+        self.driver_selection_widget.focus = self.focus == FocusUI::Driver;
+        self.result_widget.focus = self.focus == FocusUI::Result;
+    }
+
+    fn select_net(&mut self, net_name: String) {
+        let idx = self.dspf.netlist.nets_map[&net_name];
+        let net = &self.dspf.netlist.all_nets[idx];
+        let nodes: Vec<_> = net
+            .subnodes
+            .iter()
+            .map(|idx| &self.dspf.netlist.all_nodes[*idx])
+            .collect();
+        self.driver_selection_widget = MultiNodeSelectionWidget::new(nodes, "Driver node:");
+        self.selected_net = Some(net_name);
+        self.set_focus(FocusUI::Driver);
+        self.analyze();
+    }
+
+    fn analyze(&mut self) {
+        self.driver = self.driver_selection_widget.selected();
+        if let (Some(net), Some(driver)) = (&self.selected_net, &self.driver) {
+            let report = self
+                .dspf
+                .netlist
+                .get_delay_metrics(net, driver, self.miller_factor, NUM_MOMENTS)
+                .unwrap_or_default();
+            self.result_widget = DelayResultWidget::new(report);
+        } else {
+            self.result_widget = DelayResultWidget::default();
+        }
+        self.result_widget.focus = self.focus == FocusUI::Result;
+    }
+
+    fn handle_result_key(&mut self, code: KeyCode) {
+        if let KeyCode::Char(c @ '0'..='2') = code {
+            self.miller_factor = c.to_digit(10).unwrap_or(1) as f64;
+            self.analyze();
+        }
+    }
+}
+
+impl Render for DelayMainUI {
+    fn render(&mut self, frame: &mut Frame) {
+        let mut status_bar = StatusBar::default()
+            .top_left("dspf-analyzer")
+            .bottom_left(&self.dspf.as_ref().file_path);
+        frame.render_widget(&mut status_bar, frame.size());
+
+        match &self.selected_net {
+            None => {
+                frame.render_widget(&mut self.net_selection_widget, status_bar.inner);
+            }
+            Some(_) => {
+                let cols_layout = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(vec![Constraint::Fill(1), Constraint::Fill(2)])
+                    .split(status_bar.inner);
+
+                frame.render_widget(&mut self.driver_selection_widget, cols_layout[0]);
+                frame.render_widget(&mut self.result_widget, cols_layout[1])
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) -> Action {
+        match event {
+            Event::Tick => Action::None,
+            Event::Key(key_event) => {
+                if key_event.kind == crossterm::event::KeyEventKind::Press {
+                    match key_event.code {
+                        KeyCode::Left => {
+                            self.set_focus(FocusUI::Driver);
+                            Action::None
+                        }
+                        KeyCode::Right | KeyCode::Tab => {
+                            self.set_focus(match self.focus {
+                                FocusUI::Driver => FocusUI::Result,
+                                FocusUI::Result if key_event.code == KeyCode::Tab => {
+                                    FocusUI::Driver
+                                }
+                                FocusUI::Result => FocusUI::Result,
+                            });
+                            Action::None
+                        }
+                        KeyCode::Esc => Action::MainMenu,
+                        KeyCode::Enter => {
+                            if self.selected_net.is_none() {
+                                if let Some(net) = self.net_selection_widget.selected() {
+                                    self.select_net(net);
+                                }
+                            }
+                            Action::None
+                        }
+
+                        // delegate others to the currently focused widget
+                        code => {
+                            match self.selected_net {
+                                None => {
+                                    self.net_selection_widget.handle_event(event);
+                                }
+                                Some(_) => match self.focus {
+                                    FocusUI::Driver => {
+                                        self.driver_selection_widget.handle_event(event);
+                                        if self.driver_selection_widget.selected() != self.driver {
+                                            self.analyze();
+                                        }
+                                    }
+                                    FocusUI::Result => {
+                                        self.result_widget.handle_event(event);
+                                        self.handle_result_key(code);
+                                    }
+                                },
+                            };
+                            Action::None
+                        }
+                    }
+                } else {
+                    Action::None
+                }
+            }
+            Event::Mouse(_) => Action::None,
+            Event::Resize(_, _) => Action::None,
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    app::Action,
+    event::Event,
+    util::{eng_format_time, line_bar},
+};
+
+use crossterm::event::KeyCode;
+use dspf_parse::dspf::netlist::{DelayReport, SinkMoments};
+
+use ratatui::{prelude::*, widgets::*};
+
+use super::{main_menu::TableSelect, net_cap_main::focus_style};
+
+#[derive(Default)]
+pub struct DelayResultWidget {
+    pub focus: bool,
+    report: DelayReport,
+    pub sink_list: TableSelect<SinkMoments>,
+    menu_height: u16,
+}
+
+impl DelayResultWidget {
+    pub fn new(report: DelayReport) -> Self {
+        let mut table_sorted = report.table.clone();
+        table_sorted.sort_by(|a, b| b.d2m.total_cmp(&a.d2m));
+
+        let mut sink_list = TableSelect::new(table_sorted);
+
+        if !sink_list.items.is_empty() {
+            sink_list.select_state(Some(0));
+        }
+
+        Self {
+            focus: false,
+            report,
+            sink_list,
+            menu_height: 1,
+        }
+    }
+
+    fn handle_arrow(&mut self, code: KeyCode) -> Action {
+        match code {
+            KeyCode::Up => self.sink_list.up(1),
+            KeyCode::Down => self.sink_list.down(1),
+            KeyCode::PageUp => self.sink_list.up((self.menu_height - 1).into()),
+            KeyCode::PageDown => self.sink_list.down((self.menu_height - 1).into()),
+            _ => 0, // not possible
+        };
+
+        Action::None
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Action {
+        match event {
+            Event::Tick => Action::None,
+            Event::Key(key_event) => {
+                if key_event.kind == crossterm::event::KeyEventKind::Press {
+                    match key_event.code {
+                        KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => {
+                            self.handle_arrow(key_event.code)
+                        }
+                        _ => Action::None,
+                    }
+                } else {
+                    Action::None
+                }
+            }
+            Event::Mouse(_) => Action::None,
+            Event::Resize(_, _) => Action::None,
+        }
+    }
+}
+
+impl Widget for &mut DelayResultWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(2),
+                Constraint::Length(2),
+                Constraint::Fill(1),
+            ])
+            .split(area);
+
+        self.menu_height = rows_layout[2].as_size().height.saturating_sub(3);
+
+        let fs = focus_style(self.focus);
+
+        Paragraph::new(format!(
+            "\n  Miller factor: {}  [0/1/2 to change]",
+            self.report.miller_factor
+        ))
+        .render(rows_layout[0], buf);
+
+        Paragraph::new("\n  Sink delay [Elmore / D2M / S2M slew]:")
+            .style(fs.1)
+            .render(rows_layout[1], buf);
+
+        let max_delay = self
+            .sink_list
+            .items
+            .iter()
+            .map(|x| x.elmore)
+            .filter(|x| x.is_finite())
+            .fold(f64::NAN, f64::max);
+
+        let rows: Vec<_> = self
+            .sink_list
+            .items
+            .iter()
+            .map(|x| {
+                let col1 = Line::raw(&x.node);
+                let col2 = Line::raw(eng_format_time(x.elmore, max_delay));
+                let col3 = Line::raw(eng_format_time(x.d2m, max_delay));
+                let col4 = Line::raw(eng_format_time(x.s2m, max_delay));
+                let col5 = line_bar(12, x.d2m / max_delay);
+                Row::new(vec![col1, col2, col3, col4, col5])
+            })
+            .collect();
+
+        let widths = [
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(12),
+        ];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["Sink", "Elmore", "D2M", "S2M", ""]).bold())
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .border_type(fs.0)
+                    .padding(Padding::horizontal(1)),
+            )
+            .highlight_style(Style::new().reversed());
+        StatefulWidget::render(table, rows_layout[2], buf, &mut self.sink_list.state);
+    }
+}
@@ -17,6 +17,7 @@ pub struct MainMenuUI {
     pub num_nodes: usize,
     pub num_capacitors: usize,
     pub num_resistors: usize,
+    pub num_warnings: usize,
     menu: ListSelect<MainMenuOption>,
 }
 
@@ -29,6 +30,7 @@ impl MainMenuUI {
             num_nodes: dspf.netlist.all_nodes.len(),
             num_capacitors: dspf.netlist.capacitors.len(),
             num_resistors: dspf.netlist.all_nets.iter().map(|net| net.resistors.len()).sum(),
+            num_warnings: dspf.diagnostics.len(),
             menu: ListSelect::new(options.to_vec()),
         }
     }
@@ -41,7 +43,7 @@ impl Render for MainMenuUI {
 
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Length(8), Constraint::Fill(1)])
+            .constraints(vec![Constraint::Length(9), Constraint::Fill(1)])
             .split(status_bar.inner);
 
         let pad = |s| format!("{:<24}", s);
@@ -70,6 +72,13 @@ impl Render for MainMenuUI {
                 Span::raw(pad("Parasitic resistors:")),
                 Span::styled(self.num_resistors.to_string(), Style::new().gray()),
             ]),
+            Line::from(vec![
+                Span::raw(pad("Skipped lines:")),
+                match self.num_warnings {
+                    0 => Span::styled("0", Style::new().gray()),
+                    n => Span::styled(n.to_string(), Style::new().yellow()),
+                },
+            ]),
         ];
 
         frame.render_widget(
@@ -1,3 +1,5 @@
+pub mod delay_main;
+pub mod delay_result;
 pub mod layer_cap_result;
 pub mod main_menu;
 pub mod multi_node_selection;
@@ -17,6 +19,7 @@ use ratatui::{prelude::*, widgets::*};
 
 use crate::{app::Action, event::Event};
 
+use self::delay_main::DelayMainUI;
 use self::main_menu::MainMenuUI;
 use self::net_cap_main::NetCapMainUI;
 use self::res_main::ResMainUI;
@@ -26,11 +29,13 @@ pub trait Render {
     fn handle_event(&mut self, event: &Event) -> Action;
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum Window {
     Blank(BlankUI),
     MainMenu(MainMenuUI),
     NetCap(NetCapMainUI),
     Res(ResMainUI),
+    Delay(DelayMainUI),
     Progress(ProgressUI),
 }
 use Window as W;
@@ -48,6 +53,7 @@ impl Render for Window {
             W::MainMenu(ui) => ui.render(frame),
             W::NetCap(ui) => ui.render(frame),
             W::Res(ui) => ui.render(frame),
+            W::Delay(ui) => ui.render(frame),
             W::Progress(ui) => ui.render(frame),
         }
     }
@@ -57,12 +63,13 @@ impl Render for Window {
             W::MainMenu(ui) => ui.handle_event(event),
             W::NetCap(ui) => ui.handle_event(event),
             W::Res(ui) => ui.handle_event(event),
+            W::Delay(ui) => ui.handle_event(event),
             W::Progress(ui) => ui.handle_event(event),
         }
     }
 }
 
-/// Examples of possible UIs
+// Examples of possible UIs
 
 pub struct BlankUI {}
 impl Render for BlankUI {
@@ -99,7 +99,7 @@ impl NetCapMainUI {
                         .dspf
                         .netlist
                         .get_net_capacitors(net_name)
-                        .unwrap_or(NetCapReport::default()),
+                        .unwrap_or_default(),
                     None => NetCapReport::default(),
                 };
                 let layer_report = match net {
@@ -107,7 +107,7 @@ impl NetCapMainUI {
                         .dspf
                         .netlist
                         .get_layer_capacitors(&net_name, AggrNet::Total)
-                        .unwrap_or(LayerCapReport::default()),
+                        .unwrap_or_default(),
                     None => LayerCapReport::default(),
                 };
                 self.net_cap_result_widget = NetCapResultWidget::new(report);
@@ -121,7 +121,7 @@ impl NetCapMainUI {
                             .dspf
                             .netlist
                             .get_layer_capacitors(&net_name, aggr)
-                            .unwrap_or(LayerCapReport::default()),
+                            .unwrap_or_default(),
                         None => LayerCapReport::default(),
                     };
                     self.layer_cap_result_widget = LayerCapResultWidget::new(report_layers);
@@ -1,7 +1,6 @@
 use crate::{app::Action, event::Event};
 use crossterm::event::KeyCode;
 use dspf_parse::dspf::netlist::NetInfo;
-use dspf_parse::dspf::netlist::ResReport;
 use dspf_parse::dspf::Dspf;
 use ratatui::prelude::*;
 use ratatui::Frame;
@@ -106,16 +105,14 @@ impl ResMainUI {
                     self.highlight_focused();
                 }
             }
-            Action::NodesChanged => {
-                if self.selected_net.is_some() {
-                    let excluded_nodes: Vec<_> = self.input_selection_widget.menu.items.clone();
-                    self.output_selection_widget.exclude(excluded_nodes);
-
-                    if !self.input_selection_widget.menu.items.is_empty()
-                        && !self.output_selection_widget.menu.items.is_empty()
-                    {
-                        self.analyze();
-                    }
+            Action::NodesChanged if self.selected_net.is_some() => {
+                let excluded_nodes: Vec<_> = self.input_selection_widget.menu.items.clone();
+                self.output_selection_widget.exclude(excluded_nodes);
+
+                if !self.input_selection_widget.menu.items.is_empty()
+                    && !self.output_selection_widget.menu.items.is_empty()
+                {
+                    self.analyze();
                 }
             }
             _ => {}
@@ -143,7 +140,7 @@ impl ResMainUI {
                 .dspf
                 .netlist
                 .get_path_resistance(net, inputs.as_slice(), outputs.as_slice())
-                .unwrap_or(ResReport::default());
+                .unwrap_or_default();
 
             self.result_widget = ResResultWidget::new(report)
         }
@@ -198,13 +195,10 @@ impl Render for ResMainUI {
                         }
                         KeyCode::Esc => Action::MainMenu,
                         KeyCode::Enter => {
-                            match self.selected_net {
-                                None => {
-                                    if let Some(net) = self.net_selection_widget.selected() {
-                                        self.handle_action(Action::SelectResNet(net));
-                                    }
+                            if self.selected_net.is_none() {
+                                if let Some(net) = self.net_selection_widget.selected() {
+                                    self.handle_action(Action::SelectResNet(net));
                                 }
-                                Some(_) => {}
                             }
                             Action::None
                         }
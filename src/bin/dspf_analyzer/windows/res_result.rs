@@ -1,5 +1,3 @@
-use std::f64::NAN;
-
 use crate::{
     app::Action,
     event::Event,
@@ -114,7 +112,7 @@ impl Widget for &mut ResResultWidget {
             .iter()
             .max_by(|a, b| a.resistance.total_cmp(&b.resistance))
             .map(|x| x.resistance)
-            .unwrap_or(NAN);
+            .unwrap_or(f64::NAN);
 
         let rows: Vec<_> = self
             .output_list
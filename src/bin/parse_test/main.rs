@@ -1,6 +1,6 @@
 use color_eyre::Result;
 
-use dspf_parse::dspf::Dspf;
+use dspf_parse::dspf::{Dspf, LoadOptions};
 
 fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().collect();
@@ -10,7 +10,7 @@ fn main() -> Result<()> {
         file_path = &args[1];
     }
 
-    let dspf = Dspf::load(file_path, None)?;
+    let dspf = Dspf::load(file_path, &LoadOptions::default(), None)?;
 
     let idx = dspf.netlist.nets_map.get("X236/14").unwrap();
 
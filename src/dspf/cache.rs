@@ -0,0 +1,664 @@
+//! Binary cache of a parsed `Dspf`, so that files which are opened repeatedly don't need to be
+//! parsed again.
+//!
+//! The cache file starts with a header identifying the source file (size, modification time and
+//! a hash of its first and last 64 KiB) and the load options it was produced with. It is only
+//! used if all of these match, otherwise the source is parsed and the cache rewritten.
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+
+use super::{
+    netlist::{
+        Capacitor, Device, LayerInfo, Net, NetInfo, NetType, Netlist, Node, NodeType, Resistor,
+    },
+    nomdspf::{DspfInfo, Subckt},
+    Diagnostic, Dspf, LoadOptions,
+};
+
+const MAGIC: &[u8; 8] = b"DSPFCACH";
+/// Increment when the encoding of any of the types below changes
+const VERSION: u32 = 1;
+
+/// Number of bytes at the start and end of the source file that are hashed
+const HASH_SAMPLE: u64 = 64 << 10;
+
+/// Where `Dspf::load` looks for (and writes) the binary cache
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum CacheMode {
+    /// Always parse the source file
+    #[default]
+    Off,
+    /// `<file>.cache` next to the source file
+    NextToSource,
+    /// In the given directory, named after the source file and a hash of its full path
+    Dir(PathBuf),
+}
+
+/// Location of the cache file for `file_path`, None if caching is disabled
+pub fn cache_path(file_path: &str, mode: &CacheMode) -> Option<PathBuf> {
+    match mode {
+        CacheMode::Off => None,
+        CacheMode::NextToSource => Some(PathBuf::from(format!("{}.cache", file_path))),
+        CacheMode::Dir(dir) => {
+            let path = fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+            let mut hasher = Fnv::default();
+            path.hash(&mut hasher);
+            let file_name = path.file_name()?.to_string_lossy();
+            Some(dir.join(format!("{}-{:016x}.cache", file_name, hasher.finish())))
+        }
+    }
+}
+
+/// Identifies the version of the source file that a cache was created from
+#[derive(Debug, PartialEq)]
+struct SourceStamp {
+    size: u64,
+    mtime: (u64, u32),
+    hash: u64,
+}
+
+impl SourceStamp {
+    fn new(file_path: &str) -> Result<Self> {
+        let mut file = fs::File::open(file_path)?;
+        let meta = file.metadata()?;
+        let mtime = meta.modified()?.duration_since(UNIX_EPOCH)?;
+
+        let size = meta.len();
+        let mut hasher = Fnv::default();
+        let mut buf = vec![0; HASH_SAMPLE.min(size) as usize];
+        file.read_exact(&mut buf)?;
+        hasher.write(&buf);
+        if size > HASH_SAMPLE {
+            file.seek(SeekFrom::Start(size - HASH_SAMPLE.min(size - HASH_SAMPLE)))?;
+            buf.clear();
+            file.read_to_end(&mut buf)?;
+            hasher.write(&buf);
+        }
+
+        Ok(SourceStamp {
+            size,
+            mtime: (mtime.as_secs(), mtime.subsec_nanos()),
+            hash: hasher.finish(),
+        })
+    }
+}
+
+/// Load a cached netlist, returns None if there is no valid cache for this source file
+pub fn read(cache_path: &Path, file_path: &str, options: &LoadOptions) -> Result<Option<Dspf>> {
+    let Ok(data) = fs::read(cache_path) else {
+        return Ok(None);
+    };
+    let mut d = Decoder { data: &data };
+
+    if d.take(MAGIC.len())? != MAGIC || u32::decode(&mut d)? != VERSION {
+        return Ok(None);
+    }
+    let stamp = SourceStamp::new(file_path)?;
+    let cached_stamp = SourceStamp {
+        size: u64::decode(&mut d)?,
+        mtime: <(u64, u32)>::decode(&mut d)?,
+        hash: u64::decode(&mut d)?,
+    };
+    if stamp != cached_stamp || Vec::<u8>::decode(&mut d)? != encode_options(options) {
+        return Ok(None);
+    }
+
+    let info = DspfInfo::decode(&mut d)?;
+    let netlist = Netlist::decode(&mut d)?;
+    let diagnostics = Vec::<Diagnostic>::decode(&mut d)?;
+
+    Ok(Some(Dspf {
+        info,
+        file_path: file_path.to_owned(),
+        file_size: stamp.size,
+        netlist,
+        diagnostics,
+    }))
+}
+
+/// Write the cache for a netlist that was just loaded from `dspf.file_path`
+pub fn write(cache_path: &Path, dspf: &Dspf, options: &LoadOptions) -> Result<()> {
+    let stamp = SourceStamp::new(&dspf.file_path)?;
+
+    let mut e = Encoder::default();
+    e.buf.extend_from_slice(MAGIC);
+    VERSION.encode(&mut e);
+    stamp.size.encode(&mut e);
+    stamp.mtime.encode(&mut e);
+    stamp.hash.encode(&mut e);
+    encode_options(options).encode(&mut e);
+
+    dspf.info.encode(&mut e);
+    dspf.netlist.encode(&mut e);
+    dspf.diagnostics.encode(&mut e);
+
+    // write to a temporary file first, so that a concurrent reader never sees a partial cache
+    let tmp_path = cache_path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp_path, &e.buf)?;
+    fs::rename(&tmp_path, cache_path)?;
+    Ok(())
+}
+
+/// Options that change the resulting netlist, a cache is only valid for the same options
+fn encode_options(options: &LoadOptions) -> Vec<u8> {
+    let mut e = Encoder::default();
+    options.strict.encode(&mut e);
+    options.short_resistors.encode(&mut e);
+    e.buf
+}
+
+/// FNV-1a, which (unlike `DefaultHasher`) is guaranteed to be stable across Rust versions
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() < n {
+            bail!("Cache file is truncated");
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+}
+
+/// Little-endian binary encoding of the netlist types
+trait Cached: Sized {
+    fn encode(&self, e: &mut Encoder);
+    fn decode(d: &mut Decoder) -> Result<Self>;
+}
+
+macro_rules! cached_int {
+    ($($t:ty),*) => {$(
+        impl Cached for $t {
+            fn encode(&self, e: &mut Encoder) {
+                e.buf.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(d: &mut Decoder) -> Result<Self> {
+                let bytes = d.take(std::mem::size_of::<$t>())?;
+                Ok(<$t>::from_le_bytes(bytes.try_into()?))
+            }
+        }
+    )*};
+}
+
+cached_int!(u8, u32, u64, f64);
+
+impl Cached for usize {
+    fn encode(&self, e: &mut Encoder) {
+        (*self as u64).encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(u64::decode(d)?.try_into()?)
+    }
+}
+
+impl Cached for bool {
+    fn encode(&self, e: &mut Encoder) {
+        (*self as u8).encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(u8::decode(d)? != 0)
+    }
+}
+
+impl Cached for char {
+    fn encode(&self, e: &mut Encoder) {
+        (*self as u32).encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        char::from_u32(u32::decode(d)?).ok_or_else(|| eyre!("Invalid char in cache file"))
+    }
+}
+
+impl Cached for String {
+    fn encode(&self, e: &mut Encoder) {
+        self.len().encode(e);
+        e.buf.extend_from_slice(self.as_bytes());
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let len = usize::decode(d)?;
+        Ok(std::str::from_utf8(d.take(len)?)?.to_owned())
+    }
+}
+
+impl<T: Cached> Cached for Vec<T> {
+    fn encode(&self, e: &mut Encoder) {
+        self.len().encode(e);
+        for item in self {
+            item.encode(e);
+        }
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let len = usize::decode(d)?;
+        // don't trust the length for the allocation, in case the file is corrupted
+        let mut v = Vec::with_capacity(len.min(d.data.len()));
+        for _ in 0..len {
+            v.push(T::decode(d)?);
+        }
+        Ok(v)
+    }
+}
+
+impl<T: Cached> Cached for Option<T> {
+    fn encode(&self, e: &mut Encoder) {
+        self.is_some().encode(e);
+        if let Some(value) = self {
+            value.encode(e);
+        }
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        match bool::decode(d)? {
+            true => Ok(Some(T::decode(d)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<A: Cached, B: Cached> Cached for (A, B) {
+    fn encode(&self, e: &mut Encoder) {
+        self.0.encode(e);
+        self.1.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok((A::decode(d)?, B::decode(d)?))
+    }
+}
+
+impl<K: Cached + Ord + Hash + Clone, V: Cached + Clone> Cached for HashMap<K, V> {
+    fn encode(&self, e: &mut Encoder) {
+        // sorted, so that the same netlist always gives the same file
+        let mut entries: Vec<(K, V)> = self.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Vec::<(K, V)>::decode(d)?.into_iter().collect())
+    }
+}
+
+impl Cached for DspfInfo {
+    fn encode(&self, e: &mut Encoder) {
+        self.version.encode(e);
+        self.header.encode(e);
+        self.subckt.name.encode(e);
+        self.subckt.ports.encode(e);
+        self.ground_nets.encode(e);
+        self.layer_map.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(DspfInfo {
+            version: Cached::decode(d)?,
+            header: Cached::decode(d)?,
+            subckt: Subckt {
+                name: Cached::decode(d)?,
+                ports: Cached::decode(d)?,
+            },
+            ground_nets: Cached::decode(d)?,
+            layer_map: Cached::decode(d)?,
+        })
+    }
+}
+
+impl Cached for Diagnostic {
+    fn encode(&self, e: &mut Encoder) {
+        self.line.encode(e);
+        self.line_text.encode(e);
+        self.net.encode(e);
+        self.reason.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Diagnostic {
+            line: Cached::decode(d)?,
+            line_text: Cached::decode(d)?,
+            net: Cached::decode(d)?,
+            reason: Cached::decode(d)?,
+        })
+    }
+}
+
+/// The lookup maps and the per-node capacitor/device lists are not stored, they are rebuilt
+/// from the element lists when loading.
+impl Cached for Netlist {
+    fn encode(&self, e: &mut Encoder) {
+        self.layer_map.encode(e);
+        self.all_nets.encode(e);
+        self.all_nodes.encode(e);
+        self.capacitors.encode(e);
+        self.devices.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        let mut netlist = Netlist {
+            layer_map: Cached::decode(d)?,
+            ..Netlist::default()
+        };
+        for net in Vec::<Net>::decode(d)? {
+            netlist.add_net(net);
+        }
+        netlist.all_nodes = Cached::decode(d)?;
+        netlist.capacitors = Cached::decode(d)?;
+
+        // the checksum doesn't protect against a file written by another build, check all the
+        // indices so that a bad cache is an error (and parsed again) rather than a panic later
+        let num_nodes = netlist.all_nodes.len();
+        let valid = |idx: usize| match idx < num_nodes {
+            true => Ok(idx),
+            false => Err(eyre!("Invalid node index in cache file")),
+        };
+        for net in &netlist.all_nets {
+            for &idx in &net.subnodes {
+                valid(idx)?;
+            }
+            for res in &net.resistors {
+                valid(res.nodes.0)?;
+                valid(res.nodes.1)?;
+            }
+        }
+        if (netlist.all_nodes.iter()).any(|node| node.of_net >= netlist.all_nets.len()) {
+            bail!("Invalid net index in cache file");
+        }
+        for (cap_idx, cap) in netlist.capacitors.iter().enumerate() {
+            netlist.all_nodes[valid(cap.nodes.0)?]
+                .capacitors
+                .push(cap_idx);
+            netlist.all_nodes[valid(cap.nodes.1)?]
+                .capacitors
+                .push(cap_idx);
+        }
+        for device in Vec::<Device>::decode(d)? {
+            for &t in &device.terminals {
+                valid(t)?;
+            }
+            netlist.add_device(device);
+        }
+        Ok(netlist)
+    }
+}
+
+impl Cached for Net {
+    fn encode(&self, e: &mut Encoder) {
+        self.info.name.encode(e);
+        self.info.net_type.encode(e);
+        self.total_capacitance.encode(e);
+        self.subnodes.encode(e);
+        self.resistors.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Net {
+            info: NetInfo {
+                name: Cached::decode(d)?,
+                net_type: Cached::decode(d)?,
+            },
+            total_capacitance: Cached::decode(d)?,
+            subnodes: Cached::decode(d)?,
+            resistors: Cached::decode(d)?,
+        })
+    }
+}
+
+impl Cached for NetType {
+    fn encode(&self, e: &mut Encoder) {
+        let tag: u8 = match self {
+            NetType::GroundNode => 0,
+            NetType::SubcktPin => 1,
+            NetType::Other => 2,
+        };
+        tag.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        match u8::decode(d)? {
+            0 => Ok(NetType::GroundNode),
+            1 => Ok(NetType::SubcktPin),
+            2 => Ok(NetType::Other),
+            _ => bail!("Invalid net type in cache file"),
+        }
+    }
+}
+
+impl Cached for Node {
+    fn encode(&self, e: &mut Encoder) {
+        self.name.encode(e);
+        self.info.encode(e);
+        self.coord.encode(e);
+        self.of_net.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Node {
+            name: Cached::decode(d)?,
+            info: Cached::decode(d)?,
+            coord: Cached::decode(d)?,
+            capacitors: Vec::new(),
+            devices: Vec::new(),
+            of_net: Cached::decode(d)?,
+        })
+    }
+}
+
+impl Cached for NodeType {
+    fn encode(&self, e: &mut Encoder) {
+        match self {
+            NodeType::SubcktPin { pin_type, pin_cap } => {
+                0u8.encode(e);
+                pin_type.encode(e);
+                pin_cap.encode(e);
+            }
+            NodeType::InstPin {
+                inst_name,
+                pin_name,
+                pin_type,
+                pin_cap,
+            } => {
+                1u8.encode(e);
+                inst_name.encode(e);
+                pin_name.encode(e);
+                pin_type.encode(e);
+                pin_cap.encode(e);
+            }
+            NodeType::Ground => 2u8.encode(e),
+            NodeType::Other => 3u8.encode(e),
+        }
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        match u8::decode(d)? {
+            0 => Ok(NodeType::SubcktPin {
+                pin_type: Cached::decode(d)?,
+                pin_cap: Cached::decode(d)?,
+            }),
+            1 => Ok(NodeType::InstPin {
+                inst_name: Cached::decode(d)?,
+                pin_name: Cached::decode(d)?,
+                pin_type: Cached::decode(d)?,
+                pin_cap: Cached::decode(d)?,
+            }),
+            2 => Ok(NodeType::Ground),
+            3 => Ok(NodeType::Other),
+            _ => bail!("Invalid node type in cache file"),
+        }
+    }
+}
+
+impl Cached for Resistor {
+    fn encode(&self, e: &mut Encoder) {
+        self.nodes.encode(e);
+        self.value.encode(e);
+        self.layer.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Resistor {
+            nodes: Cached::decode(d)?,
+            value: Cached::decode(d)?,
+            layer: Cached::decode(d)?,
+        })
+    }
+}
+
+impl Cached for Capacitor {
+    fn encode(&self, e: &mut Encoder) {
+        self.nodes.encode(e);
+        self.value.encode(e);
+        self.layers.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Capacitor {
+            nodes: Cached::decode(d)?,
+            value: Cached::decode(d)?,
+            layers: Cached::decode(d)?,
+        })
+    }
+}
+
+impl Cached for LayerInfo {
+    fn encode(&self, e: &mut Encoder) {
+        match *self {
+            LayerInfo::Single(n) => (0u8, n).encode(e),
+            LayerInfo::Pair(n1, n2) => (1u8, (n1, n2)).encode(e),
+            LayerInfo::None => 2u8.encode(e),
+        }
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        match u8::decode(d)? {
+            0 => Ok(LayerInfo::Single(Cached::decode(d)?)),
+            1 => Ok(LayerInfo::Pair(Cached::decode(d)?, Cached::decode(d)?)),
+            2 => Ok(LayerInfo::None),
+            _ => bail!("Invalid layer info in cache file"),
+        }
+    }
+}
+
+impl Cached for Device {
+    fn encode(&self, e: &mut Encoder) {
+        self.name.encode(e);
+        self.model.encode(e);
+        self.terminals.encode(e);
+        self.params.encode(e);
+    }
+    fn decode(d: &mut Decoder) -> Result<Self> {
+        Ok(Device {
+            name: Cached::decode(d)?,
+            model: Cached::decode(d)?,
+            terminals: Cached::decode(d)?,
+            params: Cached::decode(d)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        netlist::assert_same_netlist,
+        nomdspf::{test_netlist, TEST_DSPF},
+    };
+    use super::*;
+
+    #[test]
+    fn test_cache_roundtrip() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("dspf_cache_test_{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let file_path = dir.join("inv.dspf");
+        fs::write(&file_path, TEST_DSPF)?;
+        let file_path = file_path.to_str().unwrap();
+
+        let options = LoadOptions {
+            cache: CacheMode::Dir(dir.clone()),
+            ..LoadOptions::default()
+        };
+        let cache_file = cache_path(file_path, &options.cache).unwrap();
+
+        let parsed = Dspf::load(file_path, &options, None)?;
+        assert!(cache_file.exists());
+
+        let cached = read(&cache_file, file_path, &options)?.expect("cache should be valid");
+        assert_same_netlist(&cached.netlist, &parsed.netlist);
+        assert_eq!(cached.info.header, parsed.info.header);
+        assert_eq!(cached.info.subckt.ports, parsed.info.subckt.ports);
+        assert_eq!(cached.info.layer_map, parsed.info.layer_map);
+
+        // a corrupted cache is an error, Dspf::load falls back to parsing and rewrites it
+        let data = fs::read(&cache_file)?;
+        fs::write(&cache_file, &data[..data.len() / 2])?;
+        assert!(read(&cache_file, file_path, &options).is_err());
+        assert!(Dspf::load(file_path, &options, None).is_ok());
+        assert!(read(&cache_file, file_path, &options)?.is_some());
+
+        // different options or a modified source invalidate the cache
+        let lenient = LoadOptions {
+            strict: false,
+            ..options.clone()
+        };
+        assert!(read(&cache_file, file_path, &lenient)?.is_none());
+        let shorted = LoadOptions {
+            short_resistors: Some(1e-3),
+            ..options.clone()
+        };
+        assert!(read(&cache_file, file_path, &shorted)?.is_none());
+
+        fs::write(file_path, TEST_DSPF.replace("10.0", "11.0"))?;
+        assert!(read(&cache_file, file_path, &options)?.is_none());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_bad_index() -> Result<()> {
+        let decode = |netlist: &Netlist| {
+            let mut e = Encoder::default();
+            netlist.encode(&mut e);
+            Netlist::decode(&mut Decoder { data: &e.buf })
+        };
+        let nl = test_netlist();
+        assert_same_netlist(&decode(&nl)?, &nl);
+
+        let mut bad = test_netlist();
+        bad.all_nets[1].resistors[0].nodes.1 = bad.all_nodes.len();
+        assert!(decode(&bad).is_err());
+
+        let mut bad = test_netlist();
+        bad.all_nets[1].subnodes.push(bad.all_nodes.len());
+        assert!(decode(&bad).is_err());
+
+        let mut bad = test_netlist();
+        bad.all_nodes[0].of_net = bad.all_nets.len();
+        assert!(decode(&bad).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::{HashMap, HashSet};
+
+use color_eyre::{eyre::Context, Result};
+
+use super::netlist::{LayerInfo, Net, NetType, Netlist, NodeType};
+
+/// Allowed difference between two values: `max(absolute, relative * |reference|)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    pub relative: f64,
+    pub absolute: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            relative: 0.01,
+            absolute: 1e-18,
+        }
+    }
+}
+
+impl Tolerance {
+    fn exceeded(&self, value: f64, reference: f64) -> bool {
+        (value - reference).abs() > self.absolute.max(self.relative * reference.abs())
+    }
+}
+
+/// Declared and computed total capacitance of a net
+#[derive(Debug, Clone, Default)]
+pub struct NetCapCheck {
+    pub net_name: String,
+    /// From the `*|NET` line
+    pub declared: f64,
+    /// Sum of `ground_cap`, `coupling_cap`, `internal_cap` and `pin_cap`
+    pub computed: f64,
+    /// Caps to ground nets
+    pub ground_cap: f64,
+    /// Caps to other nets
+    pub coupling_cap: f64,
+    /// Caps between two subnodes of the net
+    pub internal_cap: f64,
+    /// Pin caps of the `*|P` and `*|I` nodes
+    pub pin_cap: f64,
+}
+
+impl NetCapCheck {
+    /// Computed minus declared total, relative to the declared one
+    ///
+    /// Infinite if the declared total is zero and the computed one isn't.
+    pub fn relative_error(&self) -> f64 {
+        if self.declared == 0.0 {
+            return match self.computed == 0.0 {
+                true => 0.0,
+                false => f64::INFINITY,
+            };
+        }
+        (self.computed - self.declared) / self.declared
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CapCheckReport {
+    pub tolerance: Tolerance,
+    /// Number of nets with a declared total capacitance
+    pub checked_nets: usize,
+    /// Nets outside of the tolerance, largest relative error first
+    pub mismatches: Vec<NetCapCheck>,
+}
+
+impl Netlist {
+    /// Compare the total capacitance declared on the `*|NET` line of each (non-ground) net with
+    /// the sum of the capacitors and pin caps connected to its subnodes.
+    ///
+    /// Nets without a declared total (NaN) are not checked.
+    pub fn check_total_capacitance(&self, tolerance: Tolerance) -> CapCheckReport {
+        let mut checks: Vec<NetCapCheck> = self
+            .all_nets
+            .iter()
+            .map(|net| NetCapCheck {
+                net_name: net.info.name.clone(),
+                declared: net.total_capacitance,
+                ..Default::default()
+            })
+            .collect();
+
+        for cap in &self.capacitors {
+            let net_a = self.all_nodes[cap.nodes.0].of_net;
+            let net_b = self.all_nodes[cap.nodes.1].of_net;
+            if net_a == net_b {
+                checks[net_a].internal_cap += cap.value;
+                continue;
+            }
+            for (net, other) in [(net_a, net_b), (net_b, net_a)] {
+                match self.all_nets[other].info.net_type {
+                    NetType::GroundNode => checks[net].ground_cap += cap.value,
+                    _ => checks[net].coupling_cap += cap.value,
+                }
+            }
+        }
+
+        for node in &self.all_nodes {
+            if let NodeType::SubcktPin { pin_cap, .. } | NodeType::InstPin { pin_cap, .. } =
+                node.info
+            {
+                checks[node.of_net].pin_cap += pin_cap;
+            }
+        }
+
+        let mut report = CapCheckReport {
+            tolerance,
+            ..Default::default()
+        };
+        for (net, mut check) in self.all_nets.iter().zip(checks) {
+            if net.info.net_type == NetType::GroundNode || !check.declared.is_finite() {
+                continue;
+            }
+            report.checked_nets += 1;
+            check.computed = check.ground_cap + check.coupling_cap + check.internal_cap;
+            check.computed += check.pin_cap;
+            if tolerance.exceeded(check.computed, check.declared) {
+                report.mismatches.push(check);
+            }
+        }
+        report.mismatches.sort_by(|a, b| {
+            (b.relative_error().abs())
+                .total_cmp(&a.relative_error().abs())
+                .then_with(|| a.net_name.cmp(&b.net_name))
+        });
+
+        report
+    }
+}
+
+/// Disjoint sets with path halving and union by size
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+/// Connectivity of the resistor network of a net
+#[derive(Debug, Clone, Default)]
+pub struct NetConnectivity {
+    pub net_name: String,
+    /// Groups of subnodes connected through resistors, largest first
+    pub islands: Vec<Vec<String>>,
+    /// Subnodes without any resistor (only for nets with more than one subnode), except the
+    /// node named after the net when the parser inserted it
+    pub floating: Vec<String>,
+    /// Instance pins that can't be reached from the subckt pin(s) of the net, or for internal
+    /// nets from the output instance pin(s), or else from the largest island
+    pub unreachable_pins: Vec<String>,
+}
+
+impl NetConnectivity {
+    pub fn is_connected(&self) -> bool {
+        self.islands.len() + self.floating.len() <= 1
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConnectivityReport {
+    pub checked_nets: usize,
+    /// Nets that are not connected, with the most islands first
+    pub problems: Vec<NetConnectivity>,
+}
+
+impl Netlist {
+    /// Connected component of each subnode of the net (as a representative node index)
+    pub(super) fn net_components(&self, net: &Net) -> HashMap<usize, usize> {
+        let local: HashMap<usize, usize> = (net.subnodes.iter().enumerate())
+            .map(|(i, &idx)| (idx, i))
+            .collect();
+        let mut sets = UnionFind::new(net.subnodes.len());
+        for res in &net.resistors {
+            if let (Some(&a), Some(&b)) = (local.get(&res.nodes.0), local.get(&res.nodes.1)) {
+                sets.union(a, b);
+            }
+        }
+        (net.subnodes.iter().enumerate())
+            .map(|(i, &idx)| (idx, net.subnodes[sets.find(i)]))
+            .collect()
+    }
+
+    /// Islands, floating subnodes and unreachable instance pins of a net
+    pub fn get_net_connectivity(&self, net_name: &str) -> Result<NetConnectivity> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let components = self.net_components(net);
+
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &idx in &net.subnodes {
+            groups.entry(components[&idx]).or_default().push(idx);
+        }
+        let with_resistor: HashSet<usize> = (net.resistors.iter())
+            .flat_map(|res| [res.nodes.0, res.nodes.1])
+            .collect();
+
+        let name = |idx: usize| self.all_nodes[idx].name.clone();
+        let subnodes: Vec<usize> = (net.subnodes.iter().copied())
+            .filter(|&idx| !self.is_implicit_node(net, idx))
+            .collect();
+        let floating: Vec<String> = match subnodes.len() > 1 {
+            true => (subnodes.iter().copied())
+                .filter(|idx| !with_resistor.contains(idx))
+                .map(name)
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut islands: Vec<Vec<usize>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+        islands.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+        let is_inst_pin = |idx: usize| matches!(self.all_nodes[idx].info, NodeType::InstPin { .. });
+        let sources = |f: fn(&NodeType) -> bool| -> Vec<usize> {
+            (net.subnodes.iter().copied())
+                .filter(|&idx| f(&self.all_nodes[idx].info))
+                .map(|idx| components[&idx])
+                .collect()
+        };
+        let mut reachable = sources(|info| matches!(info, NodeType::SubcktPin { .. }));
+        if reachable.is_empty() {
+            reachable = sources(|info| matches!(info, NodeType::InstPin { pin_type: 'O', .. }));
+        }
+        if reachable.is_empty() {
+            reachable.extend(islands.first().map(|group| components[&group[0]]));
+        }
+        let unreachable_pins = (net.subnodes.iter().copied())
+            .filter(|&idx| is_inst_pin(idx) && !reachable.contains(&components[&idx]))
+            .map(name)
+            .collect();
+
+        Ok(NetConnectivity {
+            net_name: net_name.to_owned(),
+            islands: islands
+                .into_iter()
+                .map(|group| group.into_iter().map(name).collect())
+                .collect(),
+            floating,
+            unreachable_pins,
+        })
+    }
+
+    /// Connectivity of all (non-ground) nets, with only the nets that are not connected
+    pub fn lint_connectivity(&self) -> Result<ConnectivityReport> {
+        let mut report = ConnectivityReport::default();
+        for net in &self.all_nets {
+            if net.info.net_type == NetType::GroundNode {
+                continue;
+            }
+            report.checked_nets += 1;
+            let connectivity = self.get_net_connectivity(&net.info.name)?;
+            if !connectivity.is_connected() {
+                report.problems.push(connectivity);
+            }
+        }
+        report.problems.sort_by(|a, b| {
+            let parts = |c: &NetConnectivity| c.islands.len() + c.floating.len();
+            parts(b)
+                .cmp(&parts(a))
+                .then_with(|| a.net_name.cmp(&b.net_name))
+        });
+        Ok(report)
+    }
+}
+
+/// Limits for `Netlist::check_values`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueThresholds {
+    /// Resistors below this value (including zero and negative values) are reported
+    pub min_res: f64,
+    pub max_res: f64,
+    /// Capacitors below this value (negative by default) are reported
+    pub min_cap: f64,
+    pub max_cap: f64,
+}
+
+impl Default for ValueThresholds {
+    fn default() -> Self {
+        Self {
+            min_res: 1e-3,
+            max_res: 1e9,
+            min_cap: 0.0,
+            max_cap: 1e-9,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValueIssueKind {
+    /// NaN or infinite value
+    NotFinite,
+    SmallResistor,
+    LargeResistor,
+    SmallCapacitor,
+    LargeCapacitor,
+}
+
+/// Resistor or capacitor with a suspicious value
+#[derive(Debug, Clone)]
+pub struct ValueIssue {
+    pub kind: ValueIssueKind,
+    /// Net of the first node
+    pub net_name: String,
+    pub nodes: (String, String),
+    pub value: f64,
+    /// Layer name(s), "-" if unknown
+    pub layer: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ValueReport {
+    pub thresholds: ValueThresholds,
+    pub num_resistors: usize,
+    pub num_capacitors: usize,
+    /// Sorted by kind, in the order of the netlist
+    pub issues: Vec<ValueIssue>,
+}
+
+impl Netlist {
+    /// Report every resistor and capacitor whose value is outside of `thresholds`
+    pub fn check_values(&self, thresholds: ValueThresholds) -> ValueReport {
+        let mut report = ValueReport {
+            thresholds,
+            ..Default::default()
+        };
+        let node_names = |nodes: (usize, usize)| {
+            (
+                self.all_nodes[nodes.0].name.clone(),
+                self.all_nodes[nodes.1].name.clone(),
+            )
+        };
+
+        for net in &self.all_nets {
+            for res in &net.resistors {
+                report.num_resistors += 1;
+                let kind = match res.value {
+                    v if !v.is_finite() => ValueIssueKind::NotFinite,
+                    v if v < thresholds.min_res => ValueIssueKind::SmallResistor,
+                    v if v > thresholds.max_res => ValueIssueKind::LargeResistor,
+                    _ => continue,
+                };
+                report.issues.push(ValueIssue {
+                    kind,
+                    net_name: net.info.name.clone(),
+                    nodes: node_names(res.nodes),
+                    value: res.value,
+                    layer: self.layer_name(res.layer),
+                });
+            }
+        }
+
+        for cap in &self.capacitors {
+            report.num_capacitors += 1;
+            let kind = match cap.value {
+                v if !v.is_finite() => ValueIssueKind::NotFinite,
+                v if v < thresholds.min_cap => ValueIssueKind::SmallCapacitor,
+                v if v > thresholds.max_cap => ValueIssueKind::LargeCapacitor,
+                _ => continue,
+            };
+            let layer = match cap.layers {
+                LayerInfo::Single(l) => self.layer_name(Some(l)),
+                LayerInfo::Pair(l1, l2) => {
+                    format!(
+                        "{}/{}",
+                        self.layer_name(Some(l1)),
+                        self.layer_name(Some(l2))
+                    )
+                }
+                LayerInfo::None => self.layer_name(None),
+            };
+            report.issues.push(ValueIssue {
+                kind,
+                net_name: self.all_nets[self.all_nodes[cap.nodes.0].of_net]
+                    .info
+                    .name
+                    .clone(),
+                nodes: node_names(cap.nodes),
+                value: cap.value,
+                layer,
+            });
+        }
+
+        // stable, so the netlist order is kept for each kind
+        report.issues.sort_by_key(|issue| issue.kind);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dspf::nomdspf::{parse_test_netlist, test_netlist, with_internal_net, TEST_DSPF};
+
+    #[test]
+    fn test_check_total_capacitance() {
+        let mut nl = test_netlist();
+
+        // in: 3e-16 + 1e-16 + 2e-16 (pin cap) declared as 1.5e-15
+        // out: 5e-16 + 1e-16 declared as 2e-15
+        let report = nl.check_total_capacitance(Tolerance::default());
+        assert_eq!(report.checked_nets, 2);
+        let names: Vec<&str> = report
+            .mismatches
+            .iter()
+            .map(|c| c.net_name.as_str())
+            .collect();
+        assert_eq!(names, ["out", "in"]);
+        let net_in = &report.mismatches[1];
+        assert_eq!(net_in.declared, 1.5e-15);
+        assert_eq!(net_in.ground_cap, 3e-16);
+        assert_eq!(net_in.coupling_cap, 1e-16);
+        assert_eq!(net_in.internal_cap, 0.0);
+        assert_eq!(net_in.pin_cap, 2e-16);
+        assert!((net_in.computed - 6e-16).abs() < 1e-30);
+        assert!((net_in.relative_error() + 0.6).abs() < 1e-9);
+
+        let loose = Tolerance {
+            relative: 0.65,
+            absolute: 0.0,
+        };
+        let report = nl.check_total_capacitance(loose);
+        assert_eq!(report.mismatches.len(), 1);
+
+        let idx = nl.nets_map["in"];
+        nl.all_nets[idx].total_capacitance = 6.001e-16;
+        let idx = nl.nets_map["out"];
+        nl.all_nets[idx].total_capacitance = f64::NAN;
+        let report = nl.check_total_capacitance(Tolerance::default());
+        assert_eq!(report.checked_nets, 1);
+        assert!(report.mismatches.is_empty());
+
+        // a zero declared total is an infinite error, or none if nothing is connected
+        nl.all_nets[idx].total_capacitance = 0.0;
+        let idx = nl.nets_map["in"];
+        nl.all_nets[idx].total_capacitance = 1e-15;
+        let report = nl.check_total_capacitance(Tolerance::default());
+        let names: Vec<&str> = report
+            .mismatches
+            .iter()
+            .map(|c| c.net_name.as_str())
+            .collect();
+        assert_eq!(names, ["out", "in"]);
+        assert_eq!(report.mismatches[0].relative_error(), f64::INFINITY);
+        assert_eq!(NetCapCheck::default().relative_error(), 0.0);
+    }
+
+    #[test]
+    fn test_connectivity() -> Result<()> {
+        let mut nl = test_netlist();
+
+        let report = nl.lint_connectivity()?;
+        assert_eq!(report.checked_nets, 2);
+        assert!(report.problems.is_empty());
+        assert!(nl.get_net_connectivity("in")?.is_connected());
+
+        // open between in#1 and the gate pin
+        let idx = nl.nets_map["in"];
+        nl.all_nets[idx].resistors.remove(1);
+        let connectivity = nl.get_net_connectivity("in")?;
+        assert!(!connectivity.is_connected());
+        assert_eq!(connectivity.islands, [["in", "in#1"]]);
+        assert_eq!(connectivity.floating, ["XI0/MM1#g"]);
+        assert_eq!(connectivity.unreachable_pins, ["XI0/MM1#g"]);
+
+        let report = nl.lint_connectivity()?;
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].net_name, "in");
+
+        // nodes that are not connected to the inputs are ignored, unless they are outputs
+        let r = nl.get_path_resistance("in", &["in".into()], &["in#1".into()])?;
+        assert!((r.total_res - 10.0).abs() < 1e-9);
+        let err = nl.get_path_resistance("in", &["in".into()], &["XI0/MM1#g".into()]);
+        assert!(format!("{:?}", err.unwrap_err()).contains("XI0/MM1#g"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_connectivity_internal_net() -> Result<()> {
+        let mut nl = parse_test_netlist(&with_internal_net(TEST_DSPF));
+
+        // the node named n1 is only inserted by the parser
+        let report = nl.lint_connectivity()?;
+        assert_eq!(report.checked_nets, 3);
+        assert!(report.problems.is_empty());
+        let connectivity = nl.get_net_connectivity("n1")?;
+        assert!(connectivity.is_connected());
+        assert!(connectivity.floating.is_empty());
+
+        // without a subckt pin, the pins are reached from the output pin
+        let idx = nl.nets_map["n1"];
+        nl.all_nets[idx].resistors.remove(1);
+        let connectivity = nl.get_net_connectivity("n1")?;
+        assert_eq!(connectivity.islands, [["XI1/MM1#d", "n1#1"]]);
+        assert_eq!(connectivity.floating, ["XI2/MM1#g"]);
+        assert_eq!(connectivity.unreachable_pins, ["XI2/MM1#g"]);
+
+        // or from the largest island without an output pin
+        let driver = nl.find_subnode(&nl.all_nets[idx], "XI1/MM1#d")?;
+        nl.all_nodes[driver].info = NodeType::Other;
+        let connectivity = nl.get_net_connectivity("n1")?;
+        assert_eq!(connectivity.unreachable_pins, ["XI2/MM1#g"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_values() {
+        // the report types are re-exported for library users
+        use crate::dspf::{ValueIssue, ValueIssueKind, ValueReport, ValueThresholds};
+
+        let mut nl = test_netlist();
+
+        let report = nl.check_values(ValueThresholds::default());
+        assert_eq!((report.num_resistors, report.num_capacitors), (4, 3));
+        assert!(report.issues.is_empty());
+
+        let idx = nl.nets_map["out"];
+        nl.all_nets[idx].resistors[1].value = 0.0;
+        nl.capacitors[0].value = -1e-16;
+        nl.capacitors[2].value = f64::NAN;
+        let thresholds = ValueThresholds {
+            max_res: 15.0,
+            ..Default::default()
+        };
+        let report: ValueReport = nl.check_values(thresholds);
+        let kinds: Vec<ValueIssueKind> = report.issues.iter().map(|i| i.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ValueIssueKind::NotFinite,
+                ValueIssueKind::SmallResistor,
+                ValueIssueKind::LargeResistor,
+                ValueIssueKind::SmallCapacitor
+            ]
+        );
+        let c2: &ValueIssue = &report.issues[0];
+        assert_eq!(c2.nodes, ("in#1".into(), "out#1".into()));
+        assert_eq!(c2.layer, "metal1/metal2");
+        let r4 = &report.issues[1];
+        assert_eq!(r4.net_name, "out");
+        assert_eq!(r4.nodes, ("out#1".into(), "XI0/MM1#d".into()));
+        let r2 = &report.issues[2];
+        assert_eq!((r2.value, r2.layer.as_str()), (20.0, "poly"));
+    }
+}
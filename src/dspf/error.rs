@@ -0,0 +1,245 @@
+use std::fmt;
+
+/// Position in the source file where parsing failed, along with some context.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Location {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column (in characters)
+    pub column: usize,
+    /// Full text of the offending line
+    pub line_text: String,
+    /// Name of the net block being parsed, if any
+    pub net: Option<String>,
+}
+
+impl Location {
+    /// Locate `pos` (which must be a subslice of `data`) in the source text.
+    pub fn new(data: &str, pos: &str, net: Option<&str>) -> Self {
+        let offset = (pos.as_ptr() as usize).saturating_sub(data.as_ptr() as usize).min(data.len());
+        Self::from_offset(data, offset, net)
+    }
+
+    /// Locate the byte `offset` in the source text.
+    pub fn from_offset(data: &str, offset: usize, net: Option<&str>) -> Self {
+        let line = data[..offset].bytes().filter(|&b| b == b'\n').count() + 1;
+        Self::on_line(data, offset, line, net)
+    }
+
+    /// Location of the byte `offset` of `text`, which is known to be on line `line`
+    fn on_line(text: &str, offset: usize, line: usize, net: Option<&str>) -> Self {
+        let line_start = text[..offset].rfind('\n').map(|n| n + 1).unwrap_or(0);
+        let line_end = text[offset..].find('\n').map(|n| n + offset).unwrap_or(text.len());
+
+        Location {
+            line,
+            column: text[line_start..offset].chars().count() + 1,
+            line_text: text[line_start..line_end].trim_end_matches('\r').to_owned(),
+            net: net.map(|s| s.to_owned()),
+        }
+    }
+}
+
+/// Locates positions inside one block of the source text (e.g. a net block) given the line
+/// number of its first line, so that only the lines of the block are counted rather than the
+/// whole file for every diagnostic. Newlines are counted from the previously located position,
+/// which is fastest when positions come in file order.
+pub(super) struct BlockLines<'a> {
+    block: &'a str,
+    first_line: usize,
+    /// Last located offset and its line number
+    offset: usize,
+    line: usize,
+}
+
+impl<'a> BlockLines<'a> {
+    pub fn new(block: &'a str, first_line: usize) -> Self {
+        Self {
+            block,
+            first_line,
+            offset: 0,
+            line: first_line,
+        }
+    }
+
+    /// Locate `pos` (which should be a subslice of the block, positions outside of it are
+    /// clamped to the block).
+    pub fn locate(&mut self, pos: &str, net: Option<&str>) -> Location {
+        let offset = (pos.as_ptr() as usize)
+            .saturating_sub(self.block.as_ptr() as usize)
+            .min(self.block.len());
+        if offset < self.offset {
+            self.offset = 0;
+            self.line = self.first_line;
+        }
+        let newlines = self.block[self.offset..offset]
+            .bytes()
+            .filter(|&b| b == b'\n');
+        self.line += newlines.count();
+        self.offset = offset;
+        Location::on_line(self.block, offset, self.line, net)
+    }
+
+    /// Line number at the end of the block (the first line of the next block if it ends with a
+    /// newline)
+    pub fn end_line(&mut self) -> usize {
+        self.locate(&self.block[self.block.len()..], None);
+        self.line
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)?;
+        if let Some(net) = &self.net {
+            write!(f, " (in net block '{}')", net)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors returned by `Dspf::load` when the input file is broken.
+///
+/// `Dspf::load` returns a `color_eyre::Result`, use `err.downcast_ref::<DspfError>()` to get
+/// the typed error back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DspfError {
+    /// The header section (`*|DSPF`, `.SUBCKT`, `*|GROUND_NET`...) could not be parsed
+    MalformedHeader { at: Location },
+    /// No `*LAYER_MAP` section was found after the header
+    MissingLayerMap { at: Location },
+    /// A `*|NET` line or one of its `*|P`/`*|I`/`*|S` node definitions could not be parsed
+    MalformedNetBlock { at: Location },
+    /// An instance line (R, C, ...) could not be parsed
+    MalformedElement { at: Location },
+    /// An instance refers to a node that was not defined in any net block
+    UnknownNode { node: String, at: Location },
+    /// A resistor refers to a layer name that is not in the layer map
+    MissingLayer { layer: String, at: Location },
+    /// The end of the file was reached without finding the `.ENDS` statement
+    UnterminatedSubckt { at: Location },
+}
+
+impl DspfError {
+    pub fn location(&self) -> &Location {
+        match self {
+            DspfError::MalformedHeader { at }
+            | DspfError::MissingLayerMap { at }
+            | DspfError::MalformedNetBlock { at }
+            | DspfError::MalformedElement { at }
+            | DspfError::UnknownNode { at, .. }
+            | DspfError::MissingLayer { at, .. }
+            | DspfError::UnterminatedSubckt { at } => at,
+        }
+    }
+
+    /// Short description of the problem, without the location
+    pub fn reason(&self) -> String {
+        match self {
+            DspfError::MalformedHeader { .. } => String::from("malformed header"),
+            DspfError::MissingLayerMap { .. } => String::from("no *LAYER_MAP section found"),
+            DspfError::MalformedNetBlock { .. } => String::from("malformed net block"),
+            DspfError::MalformedElement { .. } => String::from("malformed instance"),
+            DspfError::UnknownNode { node, .. } => format!("unknown node '{}'", node),
+            DspfError::MissingLayer { layer, .. } => format!("layer '{}' not in layer map", layer),
+            DspfError::UnterminatedSubckt { .. } => String::from("no .ENDS statement found"),
+        }
+    }
+}
+
+impl fmt::Display for DspfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = self.location();
+        write!(f, "{}: {}\n    {}", at, self.reason(), at.line_text)
+    }
+}
+
+impl std::error::Error for DspfError {}
+
+/// Warning recorded while loading a file in lenient mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based line number
+    pub line: usize,
+    pub line_text: String,
+    pub net: Option<String>,
+    pub reason: String,
+}
+
+impl From<DspfError> for Diagnostic {
+    fn from(err: DspfError) -> Self {
+        let reason = err.reason();
+        let at = err.location();
+        Diagnostic {
+            line: at.line,
+            line_text: at.line_text.clone(),
+            net: at.net.clone(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// Collects the problems found while loading (lenient mode) or aborts on the first one (strict)
+pub(super) struct Diagnostics {
+    strict: bool,
+    pub list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            list: Vec::new(),
+        }
+    }
+
+    pub fn report(&mut self, err: DspfError) -> Result<(), DspfError> {
+        if self.strict {
+            return Err(err);
+        }
+        self.list.push(Diagnostic::from(err));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let data = "first line\nsecond line\nthird";
+        let loc = Location::new(data, &data[18..], Some("net1"));
+        assert_eq!(loc.line, 2);
+        assert_eq!(loc.column, 8);
+        assert_eq!(loc.line_text, "second line");
+        assert_eq!(loc.net.as_deref(), Some("net1"));
+
+        let loc = Location::from_offset(data, data.len(), None);
+        assert_eq!(loc.line, 3);
+        assert_eq!(loc.column, 6);
+        assert_eq!(loc.line_text, "third");
+
+        // same positions, in a block starting on the second line
+        let mut lines = BlockLines::new(&data[11..], 2);
+        assert_eq!(
+            lines.locate(&data[18..], Some("net1")),
+            Location::new(data, &data[18..], Some("net1"))
+        );
+        assert_eq!(
+            lines.locate(&data[25..], None),
+            Location::new(data, &data[25..], None)
+        );
+        assert_eq!(
+            lines.locate(&data[12..], None),
+            Location::new(data, &data[12..], None)
+        );
+        assert_eq!(lines.end_line(), 3);
+    }
+}
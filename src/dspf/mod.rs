@@ -1,8 +1,53 @@
+mod cache;
+mod checks;
+mod error;
 mod nomutil;
 
 pub mod netlist;
 mod nomdspf;
+mod sim;
+mod spef;
+mod summary;
+mod transform;
+mod writer;
+pub use cache::CacheMode;
+pub use checks::{
+    CapCheckReport, ConnectivityReport, NetCapCheck, NetConnectivity, Tolerance, ValueIssue,
+    ValueIssueKind, ValueReport, ValueThresholds,
+};
+pub use error::{Diagnostic, DspfError, Location};
 pub use nomdspf::Dspf;
+pub use sim::{
+    log_sweep, ImpedancePoint, ImpedanceReport, Integration, SinkTiming, Stimulus, TranOptions,
+    TranResult,
+};
+pub use summary::{
+    CapSortKey, DesignCapReport, DesignPiReport, DesignResReport, NetCapSummary, NetFilter,
+    NetResSummary, ResSortKey,
+};
+pub use transform::{CouplingThreshold, ExcludedCoupling, ReduceOptions, ReductionReport};
+
+/// Options for `Dspf::load`
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Abort on the first malformed line. If false, lines that can't be parsed (and instances
+    /// referring to unknown nodes) are skipped and recorded in `Dspf::diagnostics` instead.
+    pub strict: bool,
+    /// Binary cache of the parsed netlist, used instead of the source file if it is up to date
+    pub cache: CacheMode,
+    /// Short resistors below this value (merging their nodes), see `Netlist::short_resistors`
+    pub short_resistors: Option<f64>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            strict: true,
+            cache: CacheMode::Off,
+            short_resistors: None,
+        }
+    }
+}
 
 /// Load progress to be shared with another thread through Arc<Mutex>
 #[derive(Default)]
@@ -1,11 +1,23 @@
 use core::fmt;
-use std::{cmp::min, collections::HashMap, fmt::Formatter};
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    fmt::Formatter,
+};
 
 use color_eyre::{
-    eyre::{Context, ContextCompat, OptionExt},
+    eyre::{bail, Context, ContextCompat, OptionExt},
     Result,
 };
-use faer::{solvers::SpSolver, sparse::SparseColMat, Col, Side};
+use globset::{Glob, GlobSetBuilder};
+
+use faer::{
+    solvers::SpSolver,
+    sparse::{linalg::solvers::Cholesky, SparseColMat},
+    Col, ColRef, Mat, Side,
+};
+
+use super::nomutil::float;
 
 #[derive(Default)]
 pub struct Netlist {
@@ -13,6 +25,8 @@ pub struct Netlist {
     pub nets_map: HashMap<String, usize>,
     pub all_nodes: Vec<Node>,
     pub capacitors: Vec<Capacitor>,
+    pub devices: Vec<Device>,
+    pub devices_map: HashMap<String, usize>,
     pub layer_map: HashMap<u8, String>,
 }
 
@@ -26,12 +40,47 @@ impl Netlist {
         self.all_nodes.push(node);
         self.all_nodes.len() - 1
     }
+    pub fn add_device(&mut self, device: Device) -> usize {
+        let idx = self.devices.len();
+        for &node in &device.terminals {
+            if !self.all_nodes[node].devices.contains(&idx) {
+                self.all_nodes[node].devices.push(idx);
+            }
+        }
+        self.devices_map.insert(device.name.clone(), idx);
+        self.devices.push(device);
+        idx
+    }
 
     pub fn get_net(&self, net: &str) -> Result<&Net> {
         let idx = self.nets_map.get(net).context("Net name not found")?;
         Ok(&self.all_nets[*idx])
     }
 
+    pub fn get_device(&self, name: &str) -> Result<&Device> {
+        let idx = self.devices_map.get(name).context("Device name not found")?;
+        Ok(&self.devices[*idx])
+    }
+
+    /// All devices with at least one terminal connected to a subnode of the net
+    pub fn get_net_devices(&self, net_name: &str) -> Result<Vec<&Device>> {
+        let net = self.get_net(net_name)?;
+        let mut device_indices: Vec<usize> = net
+            .subnodes
+            .iter()
+            .flat_map(|idx| self.all_nodes[*idx].devices.iter().copied())
+            .collect();
+        device_indices.sort();
+        device_indices.dedup();
+        Ok(device_indices.iter().map(|idx| &self.devices[*idx]).collect())
+    }
+
+    /// The net (parasitic network) that the n-th terminal of a device is connected to
+    pub fn get_terminal_net(&self, device: &Device, terminal: usize) -> Result<&Net> {
+        let node = device.terminals.get(terminal).context("Terminal index out of range")?;
+        Ok(&self.all_nets[self.all_nodes[*node].of_net])
+    }
+
     pub fn get_net_capacitors(&self, net_name: &str) -> Result<NetCapReport> {
         let idx = self.nets_map.get(net_name).context("Net name not found")?;
         let net = &self.all_nets[*idx];
@@ -78,6 +127,14 @@ impl Netlist {
         Ok(report)
     }
 
+    /// Name of a layer from the layer map, "-" if unknown (e.g. netlists read from SPEF)
+    pub fn layer_name(&self, layer: Option<u8>) -> String {
+        layer
+            .and_then(|idx| self.layer_map.get(&idx))
+            .cloned()
+            .unwrap_or_else(|| String::from("-"))
+    }
+
     pub fn get_layer_capacitors(
         &self,
         net_name: &str,
@@ -130,10 +187,7 @@ impl Netlist {
         let mut per_layer: Vec<NetCapForLayer> = Vec::new();
         for (idx, value) in layer_caps.drain() {
             per_layer.push(NetCapForLayer {
-                layer_names: (
-                    self.layer_map[&idx.0].to_owned(),
-                    self.layer_map[&idx.1].to_owned(),
-                ),
+                layer_names: (self.layer_name(Some(idx.0)), self.layer_name(Some(idx.1))),
                 cap: value,
             });
         }
@@ -148,6 +202,9 @@ impl Netlist {
         Ok(report)
     }
 
+    /// Equivalent resistance from the (shorted) input nodes to the outputs, with the current
+    /// shared equally between the outputs. See `get_point_resistance` for the resistance between
+    /// two nodes.
     pub fn get_path_resistance(
         &self,
         net_name: &str,
@@ -177,11 +234,30 @@ impl Netlist {
             output_nodes_in_order.push(*n);
         }
 
+        // subnodes that are not connected to any input would make the matrix singular
+        let components = self.net_components(net);
+        let input_components: HashSet<usize> = input_nodes_in_order
+            .iter()
+            .map(|node| components[node])
+            .collect();
+        let unconnected: Vec<&str> = output_nodes_in_order
+            .iter()
+            .filter(|node| !input_components.contains(&components[node]))
+            .map(|&node| self.all_nodes[node].name.as_str())
+            .collect();
+        if !unconnected.is_empty() {
+            bail!(
+                "Output nodes not connected to the inputs: {}.",
+                unconnected.join(", ")
+            );
+        }
+
         let (mut output_nodes, mut other_nodes): (Vec<_>, Vec<_>) = net
             .subnodes
             .iter()
             .copied()
             .filter(|node| !input_names.contains(&self.all_nodes[*node].name))
+            .filter(|node| input_components.contains(&components[node]))
             .partition(|node| output_names.contains(&self.all_nodes[*node].name));
 
         let num_outputs = output_nodes.len();
@@ -246,18 +322,16 @@ impl Netlist {
 
         let power = v_res.to_owned().column_vector_into_diagonal() * (conductance * v_res);
 
-        let mut power_per_layer: HashMap<u8, f64> = HashMap::new();
+        let mut power_per_layer: HashMap<Option<u8>, f64> = HashMap::new();
 
         for (res, value) in net.resistors.iter().zip(power.as_slice().iter()) {
-            *power_per_layer
-                .entry(res.layer.ok_or_eyre("No layer defined for resistor")?)
-                .or_insert(0.0) += value;
+            *power_per_layer.entry(res.layer).or_insert(0.0) += value;
         }
 
         let table_layers: Vec<_> = power_per_layer
             .iter()
             .map(|(i, value)| ResForLayer {
-                layer_name: self.layer_map[i].clone(),
+                layer_name: self.layer_name(*i),
                 res: *value,
             })
             .collect();
@@ -279,6 +353,757 @@ impl Netlist {
     }
 }
 
+/// Conductance matrix of the resistor network of a net, with one subnode as the reference (its
+/// row and column removed) and factorized once, so it can be solved for any injected currents.
+///
+/// Only the subnodes connected to the reference through resistors are included.
+pub struct NetConductance {
+    /// Node index (in `Netlist::all_nodes`) for each row of the matrix
+    pub nodes: Vec<usize>,
+    /// Entries of the matrix as (row, col, value), duplicates are summed
+    pub triplets: Vec<(usize, usize, f64)>,
+    rows: HashMap<usize, usize>,
+    llt: Cholesky<usize, f64>,
+}
+
+impl NetConductance {
+    /// Row of the matrix for a node index, None for the reference and unconnected nodes
+    pub fn row(&self, node: usize) -> Option<usize> {
+        self.rows.get(&node).copied()
+    }
+
+    /// Node voltages (relative to the reference) for currents injected at each row, one column
+    /// per case
+    pub fn solve(&self, currents: Mat<f64>) -> Mat<f64> {
+        self.llt.solve(currents)
+    }
+
+    /// Columns of the inverse of the matrix (node voltages for a unit current injected at one
+    /// row) for each of `rows`, computed a few at a time to limit memory use.
+    /// `f` is called with the position in `rows` and the column.
+    pub fn inverse_columns(&self, rows: &[usize], mut f: impl FnMut(usize, ColRef<f64>)) {
+        // number of right-hand sides solved at once
+        const BLOCK: usize = 64;
+
+        let n = self.nodes.len();
+        for (block, chunk) in rows.chunks(BLOCK).enumerate() {
+            let mut currents = Mat::zeros(n, chunk.len());
+            for (j, &row) in chunk.iter().enumerate() {
+                currents[(row, j)] = 1.0;
+            }
+            let voltages = self.solve(currents);
+            for j in 0..chunk.len() {
+                f(block * BLOCK + j, voltages.as_ref().col(j));
+            }
+        }
+    }
+}
+
+impl Netlist {
+    /// Index of a subnode of the net by name
+    pub fn find_subnode(&self, net: &Net, node_name: &str) -> Result<usize> {
+        net.subnodes
+            .iter()
+            .copied()
+            .find(|&idx| self.all_nodes[idx].name == node_name)
+            .ok_or_eyre(format!("Node not found: {}.", node_name))
+    }
+
+    /// Is this the node named after the net that the parser inserts when the net block doesn't
+    /// list it, and nothing connects to it?
+    pub fn is_implicit_node(&self, net: &Net, node_idx: usize) -> bool {
+        let node = &self.all_nodes[node_idx];
+        matches!(node.info, NodeType::Other)
+            && node.name == net.info.name
+            && node.coord.is_none()
+            && node.capacitors.is_empty()
+            && node.devices.is_empty()
+            && !net.resistors.iter().any(|res| res.nodes.0 == node_idx || res.nodes.1 == node_idx)
+    }
+
+    /// Build and factorize the conductance matrix of a net, with `reference` (a node index) as
+    /// the ground node
+    pub fn net_conductance(&self, net_name: &str, reference: usize) -> Result<NetConductance> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+
+        let mut neighbors: HashMap<usize, Vec<(usize, f64)>> = HashMap::new();
+        for res in &net.resistors {
+            if res.value <= 0.0 {
+                bail!(
+                    "Resistor between {} and {} has a value of {}",
+                    self.all_nodes[res.nodes.0].name,
+                    self.all_nodes[res.nodes.1].name,
+                    res.value
+                );
+            }
+            let g = 1.0 / res.value;
+            neighbors
+                .entry(res.nodes.0)
+                .or_default()
+                .push((res.nodes.1, g));
+            neighbors
+                .entry(res.nodes.1)
+                .or_default()
+                .push((res.nodes.0, g));
+        }
+
+        // nodes reachable from the reference, in the order they are found
+        let mut nodes: Vec<usize> = Vec::new();
+        let mut rows: HashMap<usize, usize> = HashMap::new();
+        let mut stack = vec![reference];
+        let mut seen: HashSet<usize> = HashSet::from([reference]);
+        while let Some(node) = stack.pop() {
+            for &(other, _) in neighbors.get(&node).into_iter().flatten() {
+                if seen.insert(other) {
+                    rows.insert(other, nodes.len());
+                    nodes.push(other);
+                    stack.push(other);
+                }
+            }
+        }
+
+        let mut triplets: Vec<(usize, usize, f64)> = Vec::new();
+        for res in &net.resistors {
+            let g = 1.0 / res.value;
+            let (a, b) = (rows.get(&res.nodes.0), rows.get(&res.nodes.1));
+            if let Some(&a) = a {
+                triplets.push((a, a, g));
+            }
+            if let Some(&b) = b {
+                triplets.push((b, b, g));
+            }
+            if let (Some(&a), Some(&b)) = (a, b) {
+                triplets.push((a, b, -g));
+                triplets.push((b, a, -g));
+            }
+        }
+
+        let g_matrix = SparseColMat::try_new_from_triplets(nodes.len(), nodes.len(), &triplets)?;
+        let llt = g_matrix.sp_cholesky(Side::Lower)?;
+
+        Ok(NetConductance {
+            nodes,
+            triplets,
+            rows,
+            llt,
+        })
+    }
+
+    /// Resistance between two subnodes of a net (infinite if they are not connected)
+    pub fn get_point_resistance(&self, net_name: &str, node_a: &str, node_b: &str) -> Result<f64> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let a = self.find_subnode(net, node_a)?;
+        let b = self.find_subnode(net, node_b)?;
+        if a == b {
+            return Ok(0.0);
+        }
+
+        let conductance = self.net_conductance(net_name, a)?;
+        let Some(row) = conductance.row(b) else {
+            return Ok(f64::INFINITY);
+        };
+        let mut current = Mat::zeros(conductance.nodes.len(), 1);
+        current[(row, 0)] = 1.0;
+        Ok(conductance.solve(current)[(row, 0)])
+    }
+
+    /// Resistance from `reference` to every other subnode of the net, using a single
+    /// factorization of the conductance matrix. Unconnected subnodes have infinite resistance.
+    pub fn get_point_resistances(
+        &self,
+        net_name: &str,
+        reference: &str,
+    ) -> Result<Vec<NodeResistance>> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let reference = self.find_subnode(net, reference)?;
+        let conductance = self.net_conductance(net_name, reference)?;
+
+        // R from the reference to node i is the i-th diagonal element of the inverse
+        let rows: Vec<usize> = (0..conductance.nodes.len()).collect();
+        let mut diagonal = vec![0.0; rows.len()];
+        conductance.inverse_columns(&rows, |i, col| diagonal[i] = col[i]);
+
+        Ok(net
+            .subnodes
+            .iter()
+            .filter(|&&idx| idx != reference)
+            .map(|&idx| NodeResistance {
+                node: self.all_nodes[idx].name.clone(),
+                resistance: conductance
+                    .row(idx)
+                    .map_or(f64::INFINITY, |row| diagonal[row]),
+            })
+            .collect())
+    }
+
+    /// Subnodes of the net whose name matches any of the glob patterns, in the order of the net
+    pub fn select_subnodes(&self, net: &Net, patterns: &[String]) -> Result<Vec<usize>> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        let globs = builder.build()?;
+
+        let nodes: Vec<usize> = net
+            .subnodes
+            .iter()
+            .copied()
+            .filter(|&idx| globs.is_match(&self.all_nodes[idx].name))
+            .collect();
+        if nodes.is_empty() {
+            bail!("No subnode of {} matches {:?}", net.info.name, patterns);
+        }
+        Ok(nodes)
+    }
+
+    /// Effective resistance between every pair of the subnodes matching `patterns`
+    /// (see `select_subnodes`)
+    pub fn get_resistance_matrix(&self, net_name: &str, patterns: &[String]) -> Result<ResMatrix> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let nodes = self.select_subnodes(net, patterns)?;
+
+        // the first node is the reference, R_ij = Z_ii + Z_jj - 2 Z_ij with Z the inverse of
+        // the reduced conductance matrix (Z is 0 for the reference)
+        let conductance = self.net_conductance(net_name, nodes[0])?;
+        let rows: Vec<Option<usize>> = nodes.iter().map(|&n| conductance.row(n)).collect();
+        let connected: Vec<usize> = rows.iter().flatten().copied().collect();
+
+        let mut z = vec![vec![0.0; connected.len()]; connected.len()];
+        conductance.inverse_columns(&connected, |j, col| {
+            for (i, &row) in connected.iter().enumerate() {
+                z[i][j] = col[row];
+            }
+        });
+
+        // position in `connected` for each node, Err(true) for the reference and Err(false) for
+        // nodes that are not connected to it
+        let mut pos = 0;
+        let index: Vec<std::result::Result<usize, bool>> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| match row {
+                Some(_) => {
+                    pos += 1;
+                    Ok(pos - 1)
+                }
+                None => Err(i == 0),
+            })
+            .collect();
+
+        let values = (0..nodes.len())
+            .map(|i| {
+                (0..nodes.len())
+                    .map(|j| match (index[i], index[j]) {
+                        _ if i == j => 0.0,
+                        (Ok(a), Ok(b)) => z[a][a] + z[b][b] - 2.0 * z[a][b],
+                        (Ok(a), Err(true)) | (Err(true), Ok(a)) => z[a][a],
+                        _ => f64::INFINITY,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(ResMatrix {
+            net_name: net_name.to_owned(),
+            nodes: nodes
+                .iter()
+                .map(|&n| self.all_nodes[n].name.clone())
+                .collect(),
+            values,
+        })
+    }
+
+    /// Resistance from a reference node to each of the (nominally symmetric) subnodes matching
+    /// `patterns`, and the worst mismatch between them.
+    ///
+    /// The reference defaults to the subckt pin of the net (`*|P`), it is left out of the table
+    /// if it matches the patterns. Nodes that aren't connected to the reference are listed with
+    /// an infinite resistance but left out of the statistics, which need at least 2 connected
+    /// nodes.
+    pub fn get_resistance_mismatch(
+        &self,
+        net_name: &str,
+        patterns: &[String],
+        reference: Option<&str>,
+    ) -> Result<ResMismatchReport> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let mut nodes = self.select_subnodes(net, patterns)?;
+        let reference = match reference {
+            Some(name) => self.find_subnode(net, name)?,
+            None => net
+                .subnodes
+                .iter()
+                .copied()
+                .find(|&idx| matches!(self.all_nodes[idx].info, NodeType::SubcktPin { .. }))
+                .ok_or_eyre("Net has no subckt pin, a reference node is needed")?,
+        };
+
+        nodes.retain(|&n| n != reference);
+
+        let conductance = self.net_conductance(net_name, reference)?;
+        let rows: Vec<usize> = nodes.iter().filter_map(|&n| conductance.row(n)).collect();
+        let mut diagonal = vec![0.0; rows.len()];
+        conductance.inverse_columns(&rows, |i, col| diagonal[i] = col[rows[i]]);
+
+        let mut diagonal = diagonal.into_iter();
+        let table: Vec<NodeResistance> = nodes
+            .iter()
+            .map(|&n| NodeResistance {
+                node: self.all_nodes[n].name.clone(),
+                resistance: match conductance.row(n) {
+                    Some(_) => diagonal.next().unwrap_or(f64::NAN),
+                    None => f64::INFINITY,
+                },
+            })
+            .collect();
+
+        let connected: Vec<&NodeResistance> =
+            table.iter().filter(|r| r.resistance.is_finite()).collect();
+        if connected.len() < 2 {
+            bail!("At least 2 nodes connected to the reference are needed for the mismatch.");
+        }
+        let by_res =
+            |a: &&NodeResistance, b: &&NodeResistance| a.resistance.total_cmp(&b.resistance);
+        let min = connected
+            .iter()
+            .copied()
+            .min_by(by_res)
+            .cloned()
+            .unwrap_or_default();
+        let max = connected
+            .iter()
+            .copied()
+            .max_by(by_res)
+            .cloned()
+            .unwrap_or_default();
+        let mean = connected.iter().map(|r| r.resistance).sum::<f64>() / connected.len() as f64;
+
+        Ok(ResMismatchReport {
+            net_name: net_name.to_owned(),
+            reference: self.all_nodes[reference].name.clone(),
+            mean,
+            mismatch: (max.resistance - min.resistance) / mean,
+            worst_pair: (min, max),
+            table,
+        })
+    }
+}
+
+/// RC model of a net driven by an ideal voltage source at one subnode (the reference of the
+/// conductance matrix). Coupling caps to other nets are grounded and scaled by a Miller factor.
+pub struct NetRc {
+    pub driver: usize,
+    pub conductance: NetConductance,
+    /// Capacitance to ground for each row (pin caps, ground caps and scaled coupling caps)
+    pub ground_caps: Vec<f64>,
+    /// Capacitance to ground at the driver, it doesn't change the transfer functions but loads
+    /// the driver
+    pub driver_cap: f64,
+    /// Caps between two subnodes of the net, None is the driver
+    pub floating_caps: Vec<(Option<usize>, Option<usize>, f64)>,
+}
+
+impl NetRc {
+    /// Charge `C * v` for node voltages `v`, with `v_driver` the voltage of the driver
+    pub fn cap_times(&self, v: &[f64], v_driver: f64) -> Vec<f64> {
+        let mut q: Vec<f64> = self.ground_caps.iter().zip(v).map(|(c, v)| c * v).collect();
+        let voltage = |row: Option<usize>| row.map_or(v_driver, |row| v[row]);
+        for &(a, b, c) in &self.floating_caps {
+            let dv = voltage(a) - voltage(b);
+            if let Some(a) = a {
+                q[a] += c * dv;
+            }
+            if let Some(b) = b {
+                q[b] -= c * dv;
+            }
+        }
+        q
+    }
+
+    /// Moments m_1..m_order of the transfer function from the driver to every row, from the
+    /// recursion `G m_k = -C m_(k-1)` with m_0 = 1 (m_1 is minus the Elmore delay)
+    pub fn moments(&self, order: usize) -> Vec<Vec<f64>> {
+        let n = self.ground_caps.len();
+        let mut moments: Vec<Vec<f64>> = Vec::with_capacity(order);
+        let mut prev = vec![1.0; n];
+        for k in 0..order {
+            // the driver voltage is 1 for m_0 and 0 for all higher moments
+            let q = self.cap_times(&prev, if k == 0 { 1.0 } else { 0.0 });
+            let rhs = Mat::from_fn(n, 1, |i, _| -q[i]);
+            let m = self.conductance.solve(rhs);
+            prev = (0..n).map(|i| m[(i, 0)]).collect();
+            moments.push(prev.clone());
+        }
+        moments
+    }
+}
+
+impl Netlist {
+    /// RC model of a net driven at `driver` (a node index), see `NetRc`.
+    ///
+    /// Caps to nodes of ground nets are grounded, caps to other nets are grounded and multiplied
+    /// by `miller_factor` (0: quiet aggressor, 1: grounded, 2: aggressor switching in the
+    /// opposite direction). Pin caps of `*|P`/`*|I` nodes are added as loads.
+    pub fn net_rc(&self, net_name: &str, driver: usize, miller_factor: f64) -> Result<NetRc> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let conductance = self.net_conductance(net_name, driver)?;
+        let net_idx = self.all_nodes[driver].of_net;
+
+        let mut ground_caps = vec![0.0; conductance.nodes.len()];
+        let mut driver_cap = 0.0;
+        let mut floating_caps = Vec::new();
+
+        for &node_idx in &net.subnodes {
+            let row = conductance.row(node_idx);
+            let node = &self.all_nodes[node_idx];
+            let mut add_ground_cap = |value: f64| match row {
+                Some(row) => ground_caps[row] += value,
+                None if node_idx == driver => driver_cap += value,
+                None => {}
+            };
+            add_ground_cap(match node.info {
+                NodeType::SubcktPin { pin_cap, .. } | NodeType::InstPin { pin_cap, .. } => pin_cap,
+                _ => 0.0,
+            });
+
+            for cap in node.capacitors.iter().map(|&idx| &self.capacitors[idx]) {
+                let other = match cap.nodes.0 == node_idx {
+                    true => cap.nodes.1,
+                    false => cap.nodes.0,
+                };
+                let other_net = self.all_nodes[other].of_net;
+
+                if other_net == net_idx {
+                    // visited from both sides, only add it once
+                    if node_idx < other {
+                        let row_other = conductance.row(other);
+                        let connected = |n: usize, row: Option<usize>| row.is_some() || n == driver;
+                        if connected(node_idx, row) && connected(other, row_other) {
+                            floating_caps.push((row, row_other, cap.value));
+                        }
+                    }
+                } else {
+                    add_ground_cap(match self.all_nets[other_net].info.net_type {
+                        NetType::GroundNode => cap.value,
+                        _ => cap.value * miller_factor,
+                    });
+                }
+            }
+        }
+
+        Ok(NetRc {
+            driver,
+            conductance,
+            ground_caps,
+            driver_cap,
+            floating_caps,
+        })
+    }
+
+    /// Elmore delay from `driver` to each instance pin (`*|I`) of the net, see `net_rc` for the
+    /// treatment of coupling caps. Sinks that are not connected to the driver have an infinite
+    /// delay.
+    pub fn get_elmore_delay(
+        &self,
+        net_name: &str,
+        driver: &str,
+        miller_factor: f64,
+    ) -> Result<ElmoreReport> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let driver_idx = self.find_subnode(net, driver)?;
+        let rc = self.net_rc(net_name, driver_idx, miller_factor)?;
+        let m1 = rc.moments(1).remove(0);
+
+        let table = self
+            .sinks(net, driver_idx)
+            .map(|idx| SinkDelay {
+                node: self.all_nodes[idx].name.clone(),
+                delay: rc
+                    .conductance
+                    .row(idx)
+                    .map_or(f64::INFINITY, |row| -m1[row]),
+            })
+            .collect();
+
+        Ok(ElmoreReport {
+            net_name: net_name.to_owned(),
+            driver: driver.to_owned(),
+            miller_factor,
+            table,
+        })
+    }
+
+    /// Moments m_1..m_order of the driver-to-sink transfer function at each `*|I` sink, with the
+    /// two-pole delay (D2M) and slew (S2M, 10%-90%) metrics derived from m_1 and m_2.
+    ///
+    /// For a single pole with time constant tau, D2M = ln(2) tau and S2M = ln(9) tau are exact.
+    /// Unlike Elmore, D2M isn't an upper bound, but it is much closer to the 50% delay on long
+    /// resistive nets.
+    pub fn get_delay_metrics(
+        &self,
+        net_name: &str,
+        driver: &str,
+        miller_factor: f64,
+        order: usize,
+    ) -> Result<DelayReport> {
+        if order < 2 {
+            bail!("At least 2 moments are needed for the delay metrics.");
+        }
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let driver_idx = self.find_subnode(net, driver)?;
+        let rc = self.net_rc(net_name, driver_idx, miller_factor)?;
+        let moments = rc.moments(order);
+
+        let table = self
+            .sinks(net, driver_idx)
+            .map(|idx| match rc.conductance.row(idx) {
+                Some(row) => {
+                    let m: Vec<f64> = moments.iter().map(|m| m[row]).collect();
+                    let (m1, m2) = (m[0], m[1]);
+                    SinkMoments {
+                        node: self.all_nodes[idx].name.clone(),
+                        elmore: -m1,
+                        d2m: match m2 > 0.0 {
+                            true => std::f64::consts::LN_2 * m1 * m1 / m2.sqrt(),
+                            false => 0.0,
+                        },
+                        s2m: 9f64.ln() * (2.0 * m2 - m1 * m1).max(0.0).sqrt(),
+                        moments: m,
+                    }
+                }
+                None => SinkMoments {
+                    node: self.all_nodes[idx].name.clone(),
+                    moments: vec![f64::NAN; order],
+                    elmore: f64::INFINITY,
+                    d2m: f64::INFINITY,
+                    s2m: f64::INFINITY,
+                },
+            })
+            .collect();
+
+        Ok(DelayReport {
+            net_name: net_name.to_owned(),
+            driver: driver.to_owned(),
+            miller_factor,
+            table,
+        })
+    }
+
+    /// Peak noise at the sinks (`*|I` pins) of a victim net for each aggressor net switching
+    /// with a 0-100% ramp of `aggressor_slew`, while the victim is held at `driver` through
+    /// `holding_resistance`. Voltages are relative to the aggressor swing.
+    ///
+    /// The charge-sharing bound is the coupling cap over the total cap of the victim. The
+    /// RC-aware estimate is the steady-state noise with the coupling current injected during the
+    /// ramp (Devgan), reduced by `1 - exp(-slew / tau)` with tau the Elmore delay from the
+    /// holding resistance to the sink; it converges to the bound for fast aggressors.
+    pub fn get_crosstalk_noise(
+        &self,
+        victim: &str,
+        driver: &str,
+        holding_resistance: f64,
+        aggressor_slew: f64,
+    ) -> Result<NoiseReport> {
+        if !(holding_resistance >= 0.0 && aggressor_slew > 0.0) {
+            bail!("The holding resistance and aggressor slew must be positive.");
+        }
+        let net = self.get_net(victim).wrap_err("Net not found.")?;
+        let victim_idx = self.nets_map[victim];
+        let driver_idx = self.find_subnode(net, driver)?;
+        let rc = self.net_rc(victim, driver_idx, 1.0)?;
+        let n = rc.conductance.nodes.len();
+
+        // coupling caps for each aggressor as (row, value), row None is the driver
+        let mut coupling: HashMap<usize, Vec<(Option<usize>, f64)>> = HashMap::new();
+        let mut total_cap = 0.0;
+        for &node_idx in &net.subnodes {
+            let node = &self.all_nodes[node_idx];
+            if let NodeType::SubcktPin { pin_cap, .. } | NodeType::InstPin { pin_cap, .. } =
+                node.info
+            {
+                total_cap += pin_cap;
+            }
+            for cap in node.capacitors.iter().map(|&idx| &self.capacitors[idx]) {
+                let other = match cap.nodes.0 == node_idx {
+                    true => cap.nodes.1,
+                    false => cap.nodes.0,
+                };
+                let other_net = self.all_nodes[other].of_net;
+                if other_net == victim_idx {
+                    continue;
+                }
+                total_cap += cap.value;
+                if self.all_nets[other_net].info.net_type != NetType::GroundNode {
+                    let row = rc.conductance.row(node_idx);
+                    coupling
+                        .entry(other_net)
+                        .or_default()
+                        .push((row, cap.value));
+                }
+            }
+        }
+
+        let sinks: Vec<usize> = self.sinks(net, driver_idx).collect();
+        let sink_rows: Vec<Option<usize>> =
+            sinks.iter().map(|&idx| rc.conductance.row(idx)).collect();
+        let elmore = rc.moments(1).remove(0);
+        let tau: Vec<f64> = sink_rows
+            .iter()
+            .map(|row| match row {
+                Some(row) => holding_resistance * total_cap - elmore[*row],
+                None => f64::NAN,
+            })
+            .collect();
+
+        let aggressors: Vec<_> = coupling.into_iter().collect();
+        let mut table = Vec::with_capacity(aggressors.len());
+
+        // number of aggressors solved at once
+        const BLOCK: usize = 64;
+        for chunk in aggressors.chunks(BLOCK) {
+            // all of the injected current flows to ground through the holding resistance
+            let mut currents = Mat::zeros(n, chunk.len());
+            let mut total_currents = vec![0.0; chunk.len()];
+            for (j, (_, caps)) in chunk.iter().enumerate() {
+                for &(row, c) in caps {
+                    if let Some(row) = row {
+                        currents[(row, j)] += c / aggressor_slew;
+                    }
+                    total_currents[j] += c / aggressor_slew;
+                }
+            }
+            let voltages = rc.conductance.solve(currents);
+
+            for (j, (aggr_idx, caps)) in chunk.iter().enumerate() {
+                let coupling_cap: f64 = caps.iter().map(|(_, c)| c).sum();
+                let sinks: Vec<SinkNoise> = sinks
+                    .iter()
+                    .zip(&sink_rows)
+                    .zip(&tau)
+                    .map(|((&idx, row), &tau)| {
+                        let peak = match row {
+                            Some(row) => {
+                                let devgan =
+                                    holding_resistance * total_currents[j] + voltages[(*row, j)];
+                                -devgan * (-aggressor_slew / tau).exp_m1()
+                            }
+                            None => f64::NAN,
+                        };
+                        SinkNoise {
+                            node: self.all_nodes[idx].name.clone(),
+                            peak,
+                        }
+                    })
+                    .collect();
+                table.push(AggressorNoise {
+                    aggressor: self.all_nets[*aggr_idx].info.name.clone(),
+                    coupling_cap,
+                    charge_sharing: coupling_cap / total_cap,
+                    peak: sinks.iter().map(|s| s.peak).fold(0.0, f64::max),
+                    sinks,
+                });
+            }
+        }
+        table.sort_by(|a, b| {
+            b.peak
+                .total_cmp(&a.peak)
+                .then(a.aggressor.cmp(&b.aggressor))
+        });
+
+        Ok(NoiseReport {
+            victim: victim.to_owned(),
+            driver: driver.to_owned(),
+            holding_resistance,
+            aggressor_slew,
+            total_cap,
+            table,
+        })
+    }
+
+    /// Pi model (C1 - R - C2) of a net seen from `driver`, matched to the first three moments
+    /// of the driver admittance `Y(s) = y1 s + y2 s^2 + y3 s^3` (O'Brien/Savarino):
+    /// C2 = y2^2 / y3, R = -y3^2 / y2^3 and C1 = y1 - C2.
+    ///
+    /// See `net_rc` for the treatment of coupling caps. Nodes that aren't connected to the
+    /// driver are ignored, and a net without resistance to its caps is a single C1.
+    pub fn get_pi_model(
+        &self,
+        net_name: &str,
+        driver: &str,
+        miller_factor: f64,
+    ) -> Result<PiModel> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let driver_idx = self.find_subnode(net, driver)?;
+        let rc = self.net_rc(net_name, driver_idx, miller_factor)?;
+        let moments = rc.moments(2);
+
+        // the driver current charges the grounded caps, floating caps inside the net cancel out
+        let admittance = |v: &[f64]| {
+            (rc.ground_caps.iter().zip(v))
+                .map(|(c, v)| c * v)
+                .sum::<f64>()
+        };
+        let y1 = rc.driver_cap + rc.ground_caps.iter().sum::<f64>();
+        let y2 = admittance(&moments[0]);
+        let y3 = admittance(&moments[1]);
+
+        let (c1, res, c2) = match y2 < 0.0 && y3 > 0.0 {
+            true => {
+                let c2 = y2 * y2 / y3;
+                (y1 - c2, -y3 * y3 / (y2 * y2 * y2), c2)
+            }
+            false => (y1, 0.0, 0.0),
+        };
+
+        Ok(PiModel {
+            net_name: net_name.to_owned(),
+            driver: driver.to_owned(),
+            total_cap: y1,
+            c1,
+            res,
+            c2,
+        })
+    }
+
+    /// Instance pins of a net, except the driver
+    pub(super) fn sinks<'a>(
+        &'a self,
+        net: &'a Net,
+        driver: usize,
+    ) -> impl Iterator<Item = usize> + 'a {
+        net.subnodes.iter().copied().filter(move |&idx| {
+            idx != driver && matches!(self.all_nodes[idx].info, NodeType::InstPin { .. })
+        })
+    }
+}
+
+/// Compare all elements of two netlists (`total_capacitance` can be NaN, so this goes through
+/// the Debug output rather than PartialEq)
+#[cfg(test)]
+pub(crate) fn assert_same_netlist(a: &Netlist, b: &Netlist) {
+    fn same<T: fmt::Debug>(a: &[T], b: &[T]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert_eq!(format!("{:?}", a), format!("{:?}", b));
+        }
+    }
+    same(&a.all_nets, &b.all_nets);
+    same(&a.all_nodes, &b.all_nodes);
+    same(&a.capacitors, &b.capacitors);
+    same(&a.devices, &b.devices);
+    for (a, b) in a.all_nets.iter().zip(&b.all_nets) {
+        same(&a.subnodes, &b.subnodes);
+        same(&a.resistors, &b.resistors);
+    }
+    for (a, b) in a.all_nodes.iter().zip(&b.all_nodes) {
+        assert_eq!(a.capacitors, b.capacitors);
+        assert_eq!(a.devices, b.devices);
+    }
+    assert_eq!(a.nets_map, b.nets_map);
+    assert_eq!(a.devices_map, b.devices_map);
+    assert_eq!(a.layer_map, b.layer_map);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -301,6 +1126,7 @@ mod tests {
             info: NodeType::Other,
             coord: None,
             capacitors: vec![],
+            devices: vec![],
             of_net: 0,
         };
         net.subnodes.push(nl.add_node(node));
@@ -310,6 +1136,7 @@ mod tests {
             info: NodeType::Other,
             coord: None,
             capacitors: vec![],
+            devices: vec![],
             of_net: 0,
         };
         net.subnodes.push(nl.add_node(node));
@@ -319,6 +1146,7 @@ mod tests {
             info: NodeType::Other,
             coord: None,
             capacitors: vec![],
+            devices: vec![],
             of_net: 0,
         };
         net.subnodes.push(nl.add_node(node));
@@ -328,6 +1156,7 @@ mod tests {
             info: NodeType::Other,
             coord: None,
             capacitors: vec![],
+            devices: vec![],
             of_net: 0,
         };
         net.subnodes.push(nl.add_node(node));
@@ -355,12 +1184,265 @@ mod tests {
         nl.get_path_resistance("mynet", &inputs, &outputs)?;
         Ok(())
     }
+
+    /// Net "mynet" with the given subnodes (the first one is the subckt pin) and resistors
+    fn test_net(names: &[&str], resistors: &[((usize, usize), f64)]) -> Netlist {
+        let mut nl = Netlist::default();
+        let mut net = Net {
+            info: NetInfo {
+                name: String::from("mynet"),
+                net_type: NetType::SubcktPin,
+            },
+            total_capacitance: 1.2e-12,
+            subnodes: Vec::new(),
+            resistors: Vec::new(),
+        };
+        for (i, name) in names.iter().enumerate() {
+            let info = match i {
+                0 => NodeType::SubcktPin {
+                    pin_type: 'B',
+                    pin_cap: 0.0,
+                },
+                _ => NodeType::Other,
+            };
+            net.subnodes.push(nl.add_node(Node {
+                name: String::from(*name),
+                info,
+                coord: None,
+                capacitors: vec![],
+                devices: vec![],
+                of_net: 0,
+            }));
+        }
+        for &(nodes, value) in resistors {
+            net.resistors.push(Resistor {
+                nodes,
+                value,
+                layer: None,
+            });
+        }
+        nl.add_net(net);
+        nl
+    }
+
+    #[test]
+    fn test_point_resistance() -> Result<()> {
+        let mut nl = Netlist::default();
+        let mut net = Net {
+            info: NetInfo {
+                name: String::from("mynet"),
+                net_type: NetType::Other,
+            },
+            total_capacitance: 1.2e-12,
+            subnodes: Vec::new(),
+            resistors: Vec::new(),
+        };
+        for name in ["mynet", "node_1", "node_2", "node_3", "node_4"] {
+            net.subnodes.push(nl.add_node(Node {
+                name: String::from(name),
+                info: NodeType::Other,
+                coord: None,
+                capacitors: vec![],
+                devices: vec![],
+                of_net: 0,
+            }));
+        }
+        // node_1 -> node_2 -> node_3 -> node_1 is a loop, node_4 is not connected
+        for (nodes, value) in [
+            ((0, 1), 100.0),
+            ((1, 2), 200.0),
+            ((2, 3), 300.0),
+            ((3, 1), 300.0),
+        ] {
+            net.resistors.push(Resistor {
+                nodes,
+                value,
+                layer: None,
+            });
+        }
+        nl.add_net(net);
+
+        let r = nl.get_point_resistance("mynet", "mynet", "node_2")?;
+        assert!((r - 250.0).abs() < 1e-9);
+        let r = nl.get_point_resistance("mynet", "node_3", "node_1")?;
+        assert!((r - 187.5).abs() < 1e-9);
+        assert_eq!(
+            nl.get_point_resistance("mynet", "mynet", "node_4")?,
+            f64::INFINITY
+        );
+        assert!(nl.get_point_resistance("mynet", "mynet", "node_5").is_err());
+
+        let all = nl.get_point_resistances("mynet", "node_1")?;
+        let r: Vec<f64> = all.iter().map(|r| r.resistance).collect();
+        assert_eq!(all[0].node, "mynet");
+        for (r, expected) in r.iter().zip([100.0, 150.0, 187.5, f64::INFINITY]) {
+            assert!(*r == expected || (r - expected).abs() < 1e-9);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resistance_matrix() -> Result<()> {
+        // pin -> a, then two "symmetric" branches to the drains of 2 fingers
+        let nl = test_net(
+            &["mynet", "a", "XI1/MM1#d", "XI1/MM2#d", "XI1/MM1#g"],
+            &[((0, 1), 100.0), ((1, 2), 10.0), ((1, 3), 12.0)],
+        );
+        let patterns = [String::from("XI1/MM*#d")];
+
+        let m = nl.get_resistance_matrix("mynet", &patterns)?;
+        assert_eq!(m.nodes, vec!["XI1/MM1#d", "XI1/MM2#d"]);
+        assert!((m.values[0][1] - 22.0).abs() < 1e-9);
+        assert_eq!(m.values[0][1], m.values[1][0]);
+        assert_eq!(m.values[1][1], 0.0);
+
+        let m = nl.get_resistance_matrix("mynet", &[String::from("*")])?;
+        assert!((m.values[0][3] - 112.0).abs() < 1e-9);
+        assert_eq!(m.values[0][4], f64::INFINITY);
+
+        let report = nl.get_resistance_mismatch("mynet", &patterns, None)?;
+        assert_eq!(report.reference, "mynet");
+        assert_eq!(report.worst_pair.0.node, "XI1/MM1#d");
+        assert_eq!(report.worst_pair.1.node, "XI1/MM2#d");
+        assert!((report.mean - 111.0).abs() < 1e-9);
+        assert!((report.mismatch - 2.0 / 111.0).abs() < 1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resistance_mismatch() -> Result<()> {
+        // XI1/MM3#d is not connected, and the reference "a" matches the pattern
+        let nl = test_net(
+            &["mynet", "a", "XI1/MM1#d", "XI1/MM2#d", "XI1/MM3#d"],
+            &[((0, 1), 100.0), ((1, 2), 10.0), ((1, 3), 12.0)],
+        );
+        let all = [String::from("*")];
+
+        let report = nl.get_resistance_mismatch("mynet", &all, Some("a"))?;
+        let nodes: Vec<&str> = report.table.iter().map(|r| r.node.as_str()).collect();
+        assert_eq!(nodes, ["mynet", "XI1/MM1#d", "XI1/MM2#d", "XI1/MM3#d"]);
+        assert_eq!(report.table[3].resistance, f64::INFINITY);
+        assert_eq!(report.worst_pair.0.node, "XI1/MM1#d");
+        assert_eq!(report.worst_pair.1.node, "mynet");
+        assert!((report.mean - 122.0 / 3.0).abs() < 1e-9);
+        assert!(report.mismatch.is_finite());
+
+        // only one connected node left
+        let patterns = [String::from("a"), String::from("XI1/MM[13]#d")];
+        assert!(nl
+            .get_resistance_mismatch("mynet", &patterns, Some("a"))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_elmore_delay() -> Result<()> {
+        let nl = crate::dspf::nomdspf::test_netlist();
+
+        // in -R1 10-> in#1 -R2 20-> XI0/MM1#g (2e-16 pin cap)
+        // in#1 has 3e-16 to vss and 1e-16 to out#1
+        for (miller, expected) in [(0.0, 9e-15), (1.0, 1e-14), (2.0, 1.1e-14)] {
+            let report = nl.get_elmore_delay("in", "in", miller)?;
+            assert_eq!(report.table.len(), 1);
+            assert_eq!(report.table[0].node, "XI0/MM1#g");
+            assert!((report.table[0].delay - expected).abs() < 1e-24);
+        }
+        assert!(nl.get_elmore_delay("in", "in#2", 1.0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delay_metrics() -> Result<()> {
+        let nl = crate::dspf::nomdspf::test_netlist();
+
+        // without coupling, 'out' is a single pole: out -R3 5-> out#1 (5e-16) -R4 2-> sink
+        let tau = 5.0 * 5e-16;
+        let report = nl.get_delay_metrics("out", "out", 0.0, 3)?;
+        let sink = &report.table[0];
+        assert_eq!(sink.node, "XI0/MM1#d");
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6 * b.abs();
+        assert!(close(sink.moments[0], -tau));
+        assert!(close(sink.moments[1], tau * tau));
+        assert!(close(sink.moments[2], -tau * tau * tau));
+        assert!(close(sink.elmore, tau));
+        assert!(close(sink.d2m, 2f64.ln() * tau));
+        assert!(close(sink.s2m, 9f64.ln() * tau));
+
+        let report = nl.get_delay_metrics("in", "in", 1.0, 2)?;
+        let elmore = nl.get_elmore_delay("in", "in", 1.0)?;
+        assert!(close(report.table[0].elmore, elmore.table[0].delay));
+        assert!(report.table[0].d2m < report.table[0].elmore);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pi_model() -> Result<()> {
+        let nl = crate::dspf::nomdspf::test_netlist();
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-9 * b.abs();
+
+        // 'out' is a single R-C, the coupling cap to in#1 is added with the Miller factor
+        for (miller, cap) in [(0.0, 5e-16), (1.0, 6e-16)] {
+            let pi = nl.get_pi_model("out", "out", miller)?;
+            assert!(pi.c1.abs() < 1e-30);
+            assert!(close(pi.res, 5.0));
+            assert!(close(pi.c2, cap));
+            assert!(close(pi.total_cap, cap));
+        }
+
+        // in -R1 10-> in#1 (4e-16) -R2 20-> XI0/MM1#g (2e-16): R2 shields part of the far cap
+        let pi = nl.get_pi_model("in", "in", 1.0)?;
+        assert!(close(pi.total_cap, 6e-16));
+        assert!(close(pi.c1 + pi.c2, pi.total_cap));
+        assert!(pi.c1 > 0.0 && pi.c2 > 0.0);
+        assert!(pi.res > 10.0 && pi.res < 30.0);
+        // the Elmore delay of the pi model can't exceed the one of the full network
+        assert!(pi.res * pi.c2 < nl.get_elmore_delay("in", "in", 1.0)?.table[0].delay);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crosstalk_noise() -> Result<()> {
+        let nl = crate::dspf::nomdspf::test_netlist();
+
+        // 'out' is held through 100 ohm, 'in' couples 1e-16 of 6e-16 at out#1
+        let (rh, total) = (100.0, 6e-16);
+        let tau = rh * total + 5.0 * total;
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6 * b.abs();
+        for slew in [1e-13, 1e-11] {
+            let report = nl.get_crosstalk_noise("out", "out", rh, slew)?;
+            assert!(close(report.total_cap, total));
+            assert_eq!(report.table.len(), 1);
+            let aggr = &report.table[0];
+            assert_eq!(aggr.aggressor, "in");
+            assert!(close(aggr.charge_sharing, 1.0 / 6.0));
+
+            let devgan = (rh + 5.0) * 1e-16 / slew;
+            let expected = devgan * (1.0 - (-slew / tau).exp());
+            assert_eq!(aggr.sinks[0].node, "XI0/MM1#d");
+            assert!(close(aggr.sinks[0].peak, expected));
+            assert!(aggr.peak <= aggr.charge_sharing);
+        }
+
+        // a very fast aggressor gives the charge-sharing noise
+        let report = nl.get_crosstalk_noise("out", "out", rh, 1e-20)?;
+        assert!(close(report.table[0].peak, 1.0 / 6.0));
+
+        Ok(())
+    }
 }
 
 impl fmt::Debug for Netlist {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("Netlist")
-            .field("all_nets[truncated]", &&self.all_nets[..5])
+            .field(
+                "all_nets[truncated]",
+                &&self.all_nets[..min(5, self.all_nets.len())],
+            )
             .field(
                 "nets_map[truncated]",
                 &self
@@ -370,8 +1452,18 @@ impl fmt::Debug for Netlist {
                     .map(|(s, n)| (s.as_str(), *n))
                     .collect::<Vec<(&str, usize)>>(),
             )
-            .field("all_nodes[truncated]", &&self.all_nodes[..5])
-            .field("capacitors[truncated]", &&self.capacitors[..5])
+            .field(
+                "all_nodes[truncated]",
+                &&self.all_nodes[..min(5, self.all_nodes.len())],
+            )
+            .field(
+                "capacitors[truncated]",
+                &&self.capacitors[..min(5, self.capacitors.len())],
+            )
+            .field(
+                "devices[truncated]",
+                &&self.devices[..min(5, self.devices.len())],
+            )
             .finish()
     }
 }
@@ -428,12 +1520,9 @@ impl LayerCapReport {
     pub fn grouped(&self, group_by: LayerCapGroupBy) -> Vec<LayerCapGrouped> {
         let mut t: HashMap<&str, Vec<(&str, f64)>> = HashMap::new();
         for item in &self.table {
-            let (first, second) = match group_by {
-                LayerCapGroupBy::VictimLayer => (&item.layer_names.0, &item.layer_names.1),
-                LayerCapGroupBy::AggrLayer => (&item.layer_names.1, &item.layer_names.0),
-            };
+            let (first, second) = Self::first_second(item, group_by);
 
-            t.entry(first).or_insert(Vec::new()).push((second, item.cap));
+            t.entry(first).or_default().push((second, item.cap));
         }
         let mut result: Vec<LayerCapGrouped> = Vec::new();
         for (k, v) in t {
@@ -487,6 +1576,104 @@ pub struct ResReport {
     pub table_layers: Vec<ResForLayer>,
 }
 
+/// Effective resistance between each pair of a set of subnodes
+#[derive(Default, Debug)]
+pub struct ResMatrix {
+    pub net_name: String,
+    pub nodes: Vec<String>,
+    /// `values[i][j]` is the resistance between `nodes[i]` and `nodes[j]`
+    pub values: Vec<Vec<f64>>,
+}
+
+#[derive(Default, Debug)]
+pub struct ResMismatchReport {
+    pub net_name: String,
+    pub reference: String,
+    /// Resistance from the reference to each of the selected subnodes (infinite if not connected)
+    pub table: Vec<NodeResistance>,
+    /// Mean over the connected nodes
+    pub mean: f64,
+    /// Nodes with the lowest and highest resistance
+    pub worst_pair: (NodeResistance, NodeResistance),
+    /// (max - min) / mean
+    pub mismatch: f64,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct SinkDelay {
+    pub node: String,
+    pub delay: f64,
+}
+
+#[derive(Default, Debug)]
+pub struct ElmoreReport {
+    pub net_name: String,
+    pub driver: String,
+    pub miller_factor: f64,
+    pub table: Vec<SinkDelay>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct SinkMoments {
+    pub node: String,
+    /// m_1, m_2, ... (m_1 is negative)
+    pub moments: Vec<f64>,
+    pub elmore: f64,
+    pub d2m: f64,
+    pub s2m: f64,
+}
+
+#[derive(Default, Debug)]
+pub struct DelayReport {
+    pub net_name: String,
+    pub driver: String,
+    pub miller_factor: f64,
+    pub table: Vec<SinkMoments>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct PiModel {
+    pub net_name: String,
+    pub driver: String,
+    /// `c1 + c2`
+    pub total_cap: f64,
+    /// Near (driver) side cap
+    pub c1: f64,
+    pub res: f64,
+    /// Far side cap
+    pub c2: f64,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct SinkNoise {
+    pub node: String,
+    /// Peak noise relative to the aggressor swing (NaN if the sink isn't connected)
+    pub peak: f64,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct AggressorNoise {
+    pub aggressor: String,
+    pub coupling_cap: f64,
+    /// Upper bound of the noise at any node of the victim
+    pub charge_sharing: f64,
+    /// Largest RC-aware estimate over all sinks
+    pub peak: f64,
+    pub sinks: Vec<SinkNoise>,
+}
+
+#[derive(Default, Debug)]
+pub struct NoiseReport {
+    pub victim: String,
+    pub driver: String,
+    pub holding_resistance: f64,
+    pub aggressor_slew: f64,
+    /// Total cap of the victim (including pin caps)
+    pub total_cap: f64,
+    /// Sorted by decreasing peak noise
+    pub table: Vec<AggressorNoise>,
+}
+
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum NodeType {
     SubcktPin {
@@ -509,6 +1696,7 @@ pub struct Node {
     pub coord: Option<(f64, f64)>,
 
     pub capacitors: Vec<usize>,
+    pub devices: Vec<usize>,
     pub of_net: usize,
 }
 impl fmt::Debug for Node {
@@ -521,6 +1709,7 @@ impl fmt::Debug for Node {
                 "capacitors[truncated]",
                 &&self.capacitors[..min(5, self.capacitors.len())],
             )
+            .field("devices", &&self.devices)
             .field("of_net", &&self.of_net)
             .finish()
     }
@@ -576,9 +1765,37 @@ pub struct Capacitor {
     pub layers: LayerInfo,
 }
 
+/// Device instance (X/M line) from the instance section of a DSPF file
 #[derive(Debug)]
+pub struct Device {
+    pub name: String,
+    pub model: String,
+    /// Terminal nodes in the order given on the instance line (e.g. d, g, s, b)
+    pub terminals: Vec<usize>,
+    /// Instance parameters as written in the file, e.g. `("w", "1u")` or `("$X", "12.5")`
+    pub params: Vec<(String, String)>,
+}
+
+impl Device {
+    /// Numeric value of a parameter (case insensitive)
+    pub fn param(&self, name: &str) -> Option<f64> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| float(value).ok())
+            .map(|(_, value)| value)
+    }
+
+    /// Location from the `$X`/`$Y` parameters
+    pub fn coord(&self) -> Option<(f64, f64)> {
+        Some((self.param("$X")?, self.param("$Y")?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum LayerInfo {
     Single(u8),
     Pair(u8, u8),
+    #[default]
     None,
 }
@@ -1,23 +1,37 @@
 #![allow(dead_code)]
 use super::{
-    netlist::{Capacitor, LayerInfo, Net, NetInfo, NetType, Netlist, Node, NodeType, Resistor},
-    nomutil::{empty_or_comment, float, identifier, optionally_quoted_string, ws},
+    cache,
+    error::{BlockLines, Diagnostic, Diagnostics, DspfError, Location},
+    netlist::{
+        Capacitor, Device, LayerInfo, Net, NetInfo, NetType, Netlist, Node, NodeType, Resistor,
+    },
+    nomutil::{empty_or_comment, float, identifier, optionally_quoted_string, skip_statement, ws},
+    spef::{is_spef, parse_spef},
+    transform::{ExcludedCoupling, ReduceOptions, ReductionReport},
+    writer,
 };
+use memmap2::Mmap;
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag},
     character::complete::{char, digit1, line_ending, not_line_ending, one_of},
-    combinator::{map, opt, value, verify},
+    combinator::{consumed, map, map_res, not, opt, recognize, value, verify},
+    error::ErrorKind,
     multi::{many0, many1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     FindSubstring, IResult, Parser,
 };
+use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs,
+    io::{BufWriter, Write},
+};
 
-use crate::dspf::LoadStatus;
+use crate::dspf::{LoadOptions, LoadStatus};
 
-use color_eyre::{eyre::OptionExt, Result};
+use color_eyre::Result;
 
 #[derive(Debug)]
 pub struct Dspf {
@@ -25,187 +39,481 @@ pub struct Dspf {
     pub file_path: String,
     pub file_size: u64,
     pub netlist: Netlist,
+    /// Problems found while loading in lenient mode (always empty in strict mode)
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 #[derive(Debug)]
 pub struct DspfInfo {
-    version: String,
-    header: HashMap<String, String>,
-    subckt: Subckt,
-    ground_nets: Vec<String>,
-    layer_map: Option<HashMap<u8, String>>,
+    pub version: String,
+    pub header: HashMap<String, String>,
+    pub subckt: Subckt,
+    pub ground_nets: Vec<String>,
+    pub layer_map: Option<HashMap<u8, String>>,
 }
 
 #[derive(Debug)]
-struct Subckt {
-    name: String,
-    ports: Vec<String>,
+pub struct Subckt {
+    pub name: String,
+    pub ports: Vec<String>,
 }
 
 impl Dspf {
-    pub fn load(file_path: &str, status: Option<Arc<Mutex<LoadStatus>>>) -> Result<Dspf> {
-        let file_size = fs::metadata(file_path)?.len();
-        let data = fs::read_to_string(file_path)?;
-
-        let mut bytes_processed = 0_usize;
-
-        if let Some(ref s) = status {
-            let mut status = s.lock().unwrap();
-            *status = LoadStatus {
-                loaded_bytes: 0,
-                total_bytes: data.len(),
-                ..LoadStatus::default()
-            };
+    pub fn load(
+        file_path: &str,
+        options: &LoadOptions,
+        status: Option<Arc<Mutex<LoadStatus>>>,
+    ) -> Result<Dspf> {
+        let cache_path = cache::cache_path(file_path, &options.cache);
+        if let Some(ref path) = cache_path {
+            // a broken cache file is ignored, it gets overwritten below
+            if let Ok(Some(dspf)) = cache::read(path, file_path, options) {
+                if let Some(ref s) = status {
+                    let mut status = s.lock().unwrap();
+                    status.total_bytes = dspf.file_size as usize;
+                    status.loaded_bytes = status.total_bytes;
+                }
+                return Ok(dspf);
+            }
         }
-        let (mut tail, info) = parse_dspf_info(&data).map_err(|err| err.to_owned())?;
 
-        let mut instance_sections: Vec<(usize, &str)> = Vec::new();
-
-        // temporary map to look up node index when parsing R/C instances
-        let mut nodes_map: HashMap<String, usize> = HashMap::new();
-
-        let mut netlist = Netlist {
-            layer_map: info.layer_map.as_ref().ok_or_eyre("No layer map defined.")?.clone(),
-            ..Netlist::default()
+        let file = fs::File::open(file_path)?;
+        let file_size = file.metadata()?.len();
+
+        // The file is memory-mapped rather than read into a String, so that multi-GB files don't
+        // need to fit in memory next to the netlist. Pages are read in order and can be dropped
+        // by the OS once they have been parsed.
+        // SAFETY: the file must not be truncated while it is being loaded.
+        let mmap = unsafe { Mmap::map(&file)? };
+        #[cfg(unix)]
+        mmap.advise(memmap2::Advice::Sequential)?;
+        let data = std::str::from_utf8(&mmap)?;
+
+        let (info, mut netlist, diagnostics) = match is_spef(data) {
+            true => parse_spef(data, options, status)?,
+            false => parse_dspf(data, options, status)?,
         };
-
-        for ground_name in &info.ground_nets {
-            let node_idx = netlist.add_node(Node {
-                name: ground_name.to_owned(),
-                info: NodeType::Ground,
-                coord: None,
-                capacitors: Vec::new(),
-                of_net: 0, // will override below
-            });
-            nodes_map.insert(ground_name.to_owned(), node_idx);
-            let net_idx = netlist.add_net(Net {
-                info: NetInfo {
-                    name: ground_name.to_owned(),
-                    net_type: NetType::GroundNode,
-                },
-                total_capacitance: f64::NAN,
-                subnodes: vec![node_idx],
-                resistors: Vec::new(),
-            });
-            netlist.all_nodes[node_idx].of_net = net_idx;
+        if let Some(threshold) = options.short_resistors {
+            netlist.short_resistors(threshold);
         }
 
-        loop {
-            let block_start = tail.as_ptr() as usize;
+        let dspf = Dspf {
+            info,
+            file_path: file_path.to_string(),
+            file_size,
+            netlist,
+            diagnostics,
+        };
 
-            let (t, (net, nodes)) =
-                read_net_block(tail, &info.subckt.ports).map_err(|err| err.to_owned())?;
+        if let Some(ref path) = cache_path {
+            // not being able to write the cache (e.g. read-only directory) is not an error
+            let _ = cache::write(path, &dspf, options);
+        }
+        Ok(dspf)
+    }
 
-            bytes_processed += (t.as_ptr() as usize) - block_start;
+    /// Write the nets matching `patterns` (see `Netlist::subset`) as a DSPF file, with the
+    /// header of this file
+    pub fn write_subset(
+        &self,
+        file_path: &str,
+        patterns: &[String],
+        coupling: ExcludedCoupling,
+    ) -> Result<()> {
+        let netlist = self.netlist.subset(patterns, coupling)?;
+        let mut w = BufWriter::new(fs::File::create(file_path)?);
+        writer::write_dspf(&mut w, &self.info, &netlist)?;
+        w.flush()?;
+        Ok(())
+    }
 
-            let net_name = net.info.name.clone();
+    /// Write the netlist reduced with `Netlist::reduce` as a DSPF file, with the header of this
+    /// file
+    pub fn write_reduced(
+        &self,
+        file_path: &str,
+        options: &ReduceOptions,
+    ) -> Result<ReductionReport> {
+        let (netlist, report) = self.netlist.reduce(options);
+        let mut w = BufWriter::new(fs::File::create(file_path)?);
+        writer::write_dspf(&mut w, &self.info, &netlist)?;
+        w.flush()?;
+        Ok(report)
+    }
 
-            let net_idx = netlist.add_net(net);
+    /// Write the netlist as a DSPF file (header, ports and ground nets from `info`)
+    pub fn write(&self, file_path: &str) -> Result<()> {
+        let mut w = BufWriter::new(fs::File::create(file_path)?);
+        writer::write_dspf(&mut w, &self.info, &self.netlist)?;
+        w.flush()?;
+        Ok(())
+    }
+}
 
-            for mut node in nodes {
-                let name = node.name.clone();
-                node.of_net = net_idx;
-                let node_idx = netlist.add_node(node);
-                nodes_map.insert(name, node_idx);
-                netlist.all_nets[net_idx].subnodes.push(node_idx);
-            }
+/// Position of a nom error in the input (end of input if it is not known)
+fn error_pos<'a>(data: &'a str, err: &nom::Err<nom::error::Error<&'a str>>) -> &'a str {
+    match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e.input,
+        nom::Err::Incomplete(_) => &data[data.len()..],
+    }
+}
 
-            if !nodes_map.contains_key(&net_name) {
-                // special case, if the net name is not listed as a (P/I/S) subnode
-                // it is assumed implicitly and we need to insert it
-                let node_idx = netlist.add_node(Node {
-                    name: net_name.clone(),
-                    info: NodeType::Other,
-                    coord: None,
-                    capacitors: Vec::new(),
-                    of_net: net_idx,
-                });
-                nodes_map.insert(net_name.clone(), node_idx);
-                netlist.all_nodes[node_idx].of_net = net_idx;
-                netlist.all_nets[net_idx].subnodes.push(node_idx);
-            }
+fn parse_dspf(
+    data: &str,
+    options: &LoadOptions,
+    status: Option<Arc<Mutex<LoadStatus>>>,
+) -> std::result::Result<(DspfInfo, Netlist, Vec<Diagnostic>), DspfError> {
+    let mut diagnostics = Diagnostics::new(options.strict);
+
+    if let Some(ref s) = status {
+        let mut status = s.lock().unwrap();
+        *status = LoadStatus {
+            loaded_bytes: 0,
+            total_bytes: data.len(),
+            ..LoadStatus::default()
+        };
+    }
+    let (mut tail, info) = parse_dspf_info(data).map_err(|err| DspfError::MalformedHeader {
+        at: Location::new(data, error_pos(data, &err), None),
+    })?;
+
+    // temporary map to look up node index when parsing R/C instances
+    let mut nodes_map: HashMap<String, usize> = HashMap::new();
+
+    let layer_map = info
+        .layer_map
+        .as_ref()
+        .ok_or_else(|| DspfError::MissingLayerMap {
+            at: Location::new(data, tail, None),
+        })?;
+
+    let mut netlist = Netlist {
+        layer_map: layer_map.clone(),
+        ..Netlist::default()
+    };
 
-            // capture everything after this net section (until the next *|NET or end of subckt),
-            // store it away and skip ahead
-            if let Some(n) = t.find_substring("\n*|NET") {
-                instance_sections.push((123, &t[..n]));
-                tail = &t[n + 1..];
-            } else if let Some(n) = t.find_substring("\n.ENDS") {
-                instance_sections.push((123, &t[..n]));
-                // tail = &t[n + 1..];
-                break;
-            } else {
-                panic!("No .ENDS statement found");
-            }
+    for ground_name in &info.ground_nets {
+        let node_idx = netlist.add_node(Node {
+            name: ground_name.to_owned(),
+            info: NodeType::Ground,
+            coord: None,
+            capacitors: Vec::new(),
+            devices: Vec::new(),
+            of_net: 0, // will override below
+        });
+        nodes_map.insert(ground_name.to_owned(), node_idx);
+        let net_idx = netlist.add_net(Net {
+            info: NetInfo {
+                name: ground_name.to_owned(),
+                net_type: NetType::GroundNode,
+            },
+            total_capacitance: f64::NAN,
+            subnodes: vec![node_idx],
+            resistors: Vec::new(),
+        });
+        netlist.all_nodes[node_idx].of_net = net_idx;
+    }
 
-            if let Some(ref s) = status {
-                let mut status = s.lock().unwrap();
-                status.loaded_bytes = bytes_processed;
-            }
+    let layer_map_inv: HashMap<String, u8> =
+        HashMap::from_iter(layer_map.iter().map(|(k, v)| (v.clone(), *k)));
+
+    // instances referring to nodes of a net block further down in the file (e.g. coupling
+    // caps), added once all net blocks are read: (net, block, first line of the block, instance)
+    let mut deferred: Vec<(usize, &str, usize, &str, ElementDef)> = Vec::new();
+
+    // line number of the start of the next block
+    let mut line = Location::new(data, tail, None).line;
+
+    let mut is_last = false;
+    while !is_last {
+        // split off a batch of net blocks, which are parsed in parallel and then merged into the
+        // netlist in file order
+        let mut batch: Vec<(&str, bool, usize)> = Vec::new();
+        let mut batch_bytes = 0;
+        while !is_last && batch_bytes < BATCH_BYTES {
+            let (block, rest, last, terminated) = split_block(tail);
+            batch.push((block, terminated, line));
+            batch_bytes += block.len();
+            line = BlockLines::new(block, line).end_line();
+            tail = rest;
+            is_last = last;
         }
 
-        let layer_map_inv: HashMap<String, u8> = HashMap::from_iter(
-            info.layer_map
-                .as_ref()
-                .ok_or_eyre("No layer map defined.")?
-                .iter()
-                .map(|(k, v)| (v.clone(), *k)),
-        );
-
         if let Some(ref s) = status {
             let mut status = s.lock().unwrap();
-            status.total_inst_blocks = instance_sections.len();
+            status.total_nets += batch.len();
+            status.total_inst_blocks += batch.len();
         }
 
-        for (_x, inst_slice) in instance_sections {
-            let (_, instances) = parse_instances(inst_slice).map_err(|err| err.to_owned())?;
-            for inst in instances {
-                match inst {
-                    ElementDef::R {
-                        nodes,
-                        value,
-                        layer,
-                    } => {
-                        let r = Resistor {
-                            nodes: (nodes_map[&nodes.0], nodes_map[&nodes.1]),
-                            value,
-                            layer: layer.as_ref().map(|l| layer_map_inv[l]),
-                        };
-                        let net = netlist.all_nodes[r.nodes.0].of_net;
-                        netlist.all_nets[net].resistors.push(r);
+        let parsed: Vec<ParsedBlock> = batch
+            .par_iter()
+            .map(|&(block, terminated, first_line)| {
+                let parsed = parse_block(block, first_line, &info.subckt.ports, terminated);
+                if let Some(ref s) = status {
+                    s.lock().unwrap().loaded_inst_blocks += 1;
+                }
+                parsed
+            })
+            .collect();
+
+        for (&(block, _, first_line), parsed) in batch.iter().zip(parsed) {
+            let net_idx = parsed
+                .net
+                .map(|(net, nodes)| add_net_block(&mut netlist, &mut nodes_map, net, nodes));
+            let net_name = net_idx.map(|idx| netlist.all_nets[idx].info.name.clone());
+            let mut lines = BlockLines::new(block, first_line);
+            let mut at = |pos: &str| lines.locate(pos, net_name.as_deref());
+
+            for item in parsed.items {
+                let (pos, inst) = match item {
+                    BlockItem::Error(err) => {
+                        diagnostics.report(err)?;
+                        continue;
+                    }
+                    BlockItem::Element(pos, inst) => (pos, inst),
+                };
+                match add_element(&mut netlist, &nodes_map, &layer_map_inv, inst) {
+                    Ok(None) => {}
+                    // in lenient mode, the resistor is kept without layer info
+                    Ok(Some(layer)) => {
+                        diagnostics.report(DspfError::MissingLayer { layer, at: at(pos) })?
                     }
-                    ElementDef::C {
-                        nodes,
-                        value,
-                        layers,
-                    } => {
-                        let c = Capacitor {
-                            nodes: (nodes_map[&nodes.0], nodes_map[&nodes.1]),
-                            value,
-                            layers,
-                        };
-                        let nodes = c.nodes;
-                        netlist.capacitors.push(c);
-                        let cap_idx = netlist.capacitors.len() - 1;
-                        netlist.all_nodes[nodes.0].capacitors.push(cap_idx);
-                        netlist.all_nodes[nodes.1].capacitors.push(cap_idx);
+                    // elements are only parsed after a valid net block
+                    Err((inst, _)) => {
+                        deferred.push((net_idx.unwrap_or_default(), block, first_line, pos, inst))
                     }
                 }
             }
-            bytes_processed += inst_slice.len();
+
             if let Some(ref s) = status {
                 let mut status = s.lock().unwrap();
-                status.loaded_bytes = bytes_processed;
-                status.loaded_inst_blocks += 1;
+                status.loaded_bytes =
+                    (block.as_ptr() as usize - data.as_ptr() as usize) + block.len();
+                status.loaded_nets += 1;
             }
         }
+    }
 
-        Ok(Dspf {
-            info,
-            file_path: file_path.to_string(),
-            file_size,
-            netlist,
-        })
+    for (net_idx, block, first_line, pos, inst) in deferred {
+        let net_name = netlist.all_nets[net_idx].info.name.clone();
+        let at = || BlockLines::new(block, first_line).locate(pos, Some(&net_name));
+        match add_element(&mut netlist, &nodes_map, &layer_map_inv, inst) {
+            Ok(None) => {}
+            Ok(Some(layer)) => diagnostics.report(DspfError::MissingLayer { layer, at: at() })?,
+            Err((_, node)) => diagnostics.report(DspfError::UnknownNode { node, at: at() })?,
+        }
+    }
+
+    Ok((info, netlist, diagnostics.list))
+}
+
+/// Net blocks are parsed in parallel in batches of about this size, so that only the parsed
+/// elements of one batch are held in memory before they are added to the netlist
+const BATCH_BYTES: usize = 32 << 20;
+
+/// Split off the next net block, including the instance lines following it (until the next
+/// `*|NET` or the end of the subckt).
+///
+/// Returns (block, rest, is_last, is_terminated), where is_terminated is false if the end of the
+/// file was reached without finding `.ENDS`.
+fn split_block(tail: &str) -> (&str, &str, bool, bool) {
+    let block_end = tail.find_substring("\n*|NET");
+    let is_last = block_end.is_none();
+    match block_end.or_else(|| tail.find_substring("\n.ENDS")) {
+        Some(n) => (&tail[..n + 1], &tail[n + 1..], is_last, true),
+        None => (tail, &tail[tail.len()..], true, false),
+    }
+}
+
+enum BlockItem<'a> {
+    Element(&'a str, ElementDef),
+    Error(DspfError),
+}
+
+/// Net block and its instances, parsed independently of the rest of the file
+struct ParsedBlock<'a> {
+    net: Option<(Net, Vec<Node>)>,
+    /// Elements and problems in the order they appear in the file
+    items: Vec<BlockItem<'a>>,
+}
+
+/// Parse one block, `first_line` is the line number of its first line in the file (only the
+/// lines of the block are counted to locate the problems)
+fn parse_block<'a>(
+    block: &'a str,
+    first_line: usize,
+    ports: &[String],
+    terminated: bool,
+) -> ParsedBlock<'a> {
+    let mut items = Vec::new();
+    let mut lines = BlockLines::new(block, first_line);
+
+    let (section, net) = match read_net_block(block, ports) {
+        Ok((t, net)) => (Some(t), Some(net)),
+        Err(err) => {
+            // the whole block up to the next *|NET is skipped in lenient mode
+            items.push(BlockItem::Error(DspfError::MalformedNetBlock {
+                at: lines.locate(error_pos(block, &err), None),
+            }));
+            (None, None)
+        }
+    };
+    let net_name = net.as_ref().map(|(net, _)| net.info.name.as_str());
+    let mut at = |pos: &str| lines.locate(pos, net_name);
+
+    let mut rest = section.unwrap_or_default();
+    while !rest.is_empty() {
+        // parse_instances stops at the first line it doesn't understand
+        let tail = match parse_instances(rest) {
+            Ok((tail, parsed)) => {
+                items.extend(
+                    parsed
+                        .into_iter()
+                        .map(|(pos, inst)| BlockItem::Element(pos, inst)),
+                );
+                tail
+            }
+            Err(err) => {
+                items.push(BlockItem::Error(DspfError::MalformedElement {
+                    at: at(error_pos(block, &err)),
+                }));
+                break;
+            }
+        };
+        if tail.is_empty() {
+            break;
+        }
+        items.push(BlockItem::Error(DspfError::MalformedElement {
+            at: at(tail),
+        }));
+        rest = skip_statement(tail);
+    }
+
+    if !terminated {
+        items.push(BlockItem::Error(DspfError::UnterminatedSubckt {
+            at: at(&block[block.len()..]),
+        }));
+    }
+
+    ParsedBlock { net, items }
+}
+
+/// Add a net and its subnodes to the netlist
+fn add_net_block(
+    netlist: &mut Netlist,
+    nodes_map: &mut HashMap<String, usize>,
+    net: Net,
+    nodes: Vec<Node>,
+) -> usize {
+    let net_name = net.info.name.clone();
+
+    let net_idx = netlist.add_net(net);
+
+    for mut node in nodes {
+        let name = node.name.clone();
+        node.of_net = net_idx;
+        let node_idx = netlist.add_node(node);
+        nodes_map.insert(name, node_idx);
+        netlist.all_nets[net_idx].subnodes.push(node_idx);
+    }
+
+    if let Entry::Vacant(entry) = nodes_map.entry(net_name) {
+        // special case, if the net name is not listed as a (P/I/S) subnode
+        // it is assumed implicitly and we need to insert it
+        let node_idx = netlist.add_node(Node {
+            name: entry.key().clone(),
+            info: NodeType::Other,
+            coord: None,
+            capacitors: Vec::new(),
+            devices: Vec::new(),
+            of_net: net_idx,
+        });
+        entry.insert(node_idx);
+        netlist.all_nets[net_idx].subnodes.push(node_idx);
+    }
+    net_idx
+}
+
+/// Resolve the node names of an instance and add it to the netlist.
+///
+/// Returns the name of the layer if it is not in the layer map (the resistor is added without
+/// layer info), or gives the instance back along with the first unknown node name.
+fn add_element(
+    netlist: &mut Netlist,
+    nodes_map: &HashMap<String, usize>,
+    layer_map_inv: &HashMap<String, u8>,
+    inst: ElementDef,
+) -> std::result::Result<Option<String>, (ElementDef, String)> {
+    let node_idx = |name: &String| nodes_map.get(name).copied().ok_or_else(|| name.clone());
+
+    match inst {
+        ElementDef::R {
+            ref nodes,
+            value,
+            ref layer,
+        } => {
+            let nodes = match (node_idx(&nodes.0), node_idx(&nodes.1)) {
+                (Ok(a), Ok(b)) => (a, b),
+                (Err(node), _) | (_, Err(node)) => return Err((inst, node)),
+            };
+            let (layer, missing) = match layer {
+                Some(l) => match layer_map_inv.get(l) {
+                    Some(layer) => (Some(*layer), None),
+                    None => (None, Some(l.clone())),
+                },
+                None => (None, None),
+            };
+            let r = Resistor {
+                nodes,
+                value,
+                layer,
+            };
+            let net = netlist.all_nodes[r.nodes.0].of_net;
+            netlist.all_nets[net].resistors.push(r);
+            Ok(missing)
+        }
+        ElementDef::C {
+            ref nodes,
+            value,
+            layers,
+        } => {
+            let nodes = match (node_idx(&nodes.0), node_idx(&nodes.1)) {
+                (Ok(a), Ok(b)) => (a, b),
+                (Err(node), _) | (_, Err(node)) => return Err((inst, node)),
+            };
+            let c = Capacitor {
+                nodes,
+                value,
+                layers,
+            };
+            netlist.capacitors.push(c);
+            let cap_idx = netlist.capacitors.len() - 1;
+            netlist.all_nodes[nodes.0].capacitors.push(cap_idx);
+            netlist.all_nodes[nodes.1].capacitors.push(cap_idx);
+            Ok(None)
+        }
+        ElementDef::X { ref terminals, .. } => {
+            let terminals = match terminals.iter().map(node_idx).collect() {
+                Ok(terminals) => terminals,
+                Err(node) => return Err((inst, node)),
+            };
+            let ElementDef::X {
+                name,
+                model,
+                params,
+                ..
+            } = inst
+            else {
+                unreachable!()
+            };
+            netlist.add_device(Device {
+                name,
+                model,
+                terminals,
+                params,
+            });
+            Ok(None)
+        }
     }
 }
 
@@ -213,7 +521,7 @@ impl Dspf {
 fn test_dspf() -> Result<()> {
     let file_path = "DSPF/nmos_trcp70.dspf";
 
-    let _dspf = Dspf::load(file_path, None);
+    let _dspf = Dspf::load(file_path, &LoadOptions::default(), None);
     // dbg!(dspf);
 
     Ok(())
@@ -223,7 +531,7 @@ fn test_dspf() -> Result<()> {
 fn test_r_report() -> Result<()> {
     let file_path = "DSPF/nmos_trcp70.dspf";
 
-    let dspf = Dspf::load(file_path, None)?;
+    let dspf = Dspf::load(file_path, &LoadOptions::default(), None)?;
 
     let nl = dspf.netlist;
     let net = &nl.all_nets[nl.nets_map["ngate"]];
@@ -240,13 +548,284 @@ fn test_r_report() -> Result<()> {
         })
         .collect();
 
-    let report = nl.get_path_resistance("ngate", &vec![String::from("ngate")], &node_names)?;
+    let report = nl.get_path_resistance("ngate", &[String::from("ngate")], &node_names)?;
 
     dbg!(report);
 
     Ok(())
 }
 
+/// Netlist of `TEST_DSPF`, for tests of the netlist functions
+#[cfg(test)]
+pub(super) fn test_netlist() -> Netlist {
+    parse_test_netlist(TEST_DSPF)
+}
+
+#[cfg(test)]
+pub(super) fn parse_test_netlist(dspf: &str) -> Netlist {
+    parse_dspf(dspf, &LoadOptions::default(), None).unwrap().1
+}
+
+/// `TEST_DSPF` with an internal net `n1`, which has no subckt pin and no node named after it
+#[cfg(test)]
+pub(super) fn with_internal_net(dspf: &str) -> String {
+    let net = "\
+*|NET n1 1e-16
+*|I (XI1/MM1#d XI1/MM1 d O 0 0.5 2.0)
+*|I (XI2/MM1#g XI2/MM1 g I 0 0.5 3.0)
+*|S (n1#1 0.5 2.5)
+R5 XI1/MM1#d n1#1 1.0
+R6 n1#1 XI2/MM1#g 1.0
+";
+    dspf.replace("XI0/MM1 XI0/MM1#d", &format!("{}XI0/MM1 XI0/MM1#d", net))
+}
+
+#[cfg(test)]
+pub(super) const TEST_DSPF: &str = "\
+*|DSPF 1.3
+*|DESIGN \"inv\"
+*|VENDOR \"Siemens\"
+*|PROGRAM \"Calibre xACT\"
+*|DIVIDER /
+*|DELIMITER #
+
+.SUBCKT inv in out vdd vss
+*|GROUND_NET vss
+
+*LAYER_MAP
+*0 GROUND
+*1 poly
+*2 metal1
+*3 metal2
+
+*|NET in 1.5e-15
+*|P (in I 0 0.0 1.0)
+*|I (XI0/MM1#g XI0/MM1 g I 2e-16 0.5 1.0)
+*|S (in#1 0.2 1.0)
+R1 in in#1 10.0 $metal1 $lvl=2
+R2 in#1 XI0/MM1#g 20.0 $poly $lvl=1
+C1 in#1 vss 3e-16 $lvl=2
+C2 in#1 out#1 1e-16 $lvl1=2 $lvl2=3
+
+*|NET out 2e-15
+*|P (out O 0 2.0 1.0)
+*|I (XI0/MM1#d XI0/MM1 d B 0 0.5 1.2)
+*|S (out#1 1.5 1.0)
+R3 out out#1 5.0 $metal2 $lvl=3
+R4 out#1 XI0/MM1#d 2.0 $metal1 $lvl=2
+C3 out#1 vss 5e-16 $lvl=3
+XI0/MM1 XI0/MM1#d XI0/MM1#g vss vss nch w=1u l=100n nf=2 $X=0.5 $Y=1.1
+.ENDS
+";
+
+#[test]
+fn test_parse_dspf() -> Result<()> {
+    let (_info, nl, _) = parse_dspf(TEST_DSPF, &LoadOptions::default(), None)?;
+
+    assert_eq!(nl.all_nets.len(), 3);
+    assert_eq!(nl.all_nodes.len(), 7);
+    assert_eq!(nl.capacitors.len(), 3);
+    assert_eq!(nl.get_net("in")?.resistors.len(), 2);
+    assert_eq!(nl.get_net("out")?.resistors.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_load_mmap() -> Result<()> {
+    let file_path = std::env::temp_dir().join(format!("test_load_{}.dspf", std::process::id()));
+    fs::write(&file_path, TEST_DSPF)?;
+
+    let status = Arc::new(Mutex::new(LoadStatus::default()));
+    let dspf = Dspf::load(
+        file_path.to_str().unwrap(),
+        &LoadOptions::default(),
+        Some(status.clone()),
+    );
+    fs::remove_file(&file_path)?;
+    let dspf = dspf?;
+
+    assert_eq!(dspf.file_size, TEST_DSPF.len() as u64);
+    // C2 couples to a node of the next net block
+    assert_eq!(dspf.netlist.capacitors.len(), 3);
+
+    let status = status.lock().unwrap();
+    assert_eq!(status.total_bytes, TEST_DSPF.len());
+    assert_eq!(status.loaded_nets, 2);
+    assert_eq!(status.loaded_inst_blocks, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_write_subset() -> Result<()> {
+    let (info, netlist, diagnostics) = parse_dspf(TEST_DSPF, &LoadOptions::default(), None)?;
+    let dspf = Dspf {
+        info,
+        file_path: String::new(),
+        file_size: 0,
+        netlist,
+        diagnostics,
+    };
+
+    let file_path = std::env::temp_dir().join(format!("test_subset_{}.dspf", std::process::id()));
+    let file_path = file_path.to_str().unwrap();
+    dspf.write_subset(file_path, &[String::from("out")], ExcludedCoupling::Ground)?;
+    let subset = Dspf::load(file_path, &LoadOptions::default(), None);
+    fs::remove_file(file_path)?;
+    let subset = subset?;
+
+    assert_eq!(subset.info.header, dspf.info.header);
+    assert_eq!(subset.netlist.all_nets.len(), 2);
+    assert_eq!(subset.netlist.capacitors.len(), 2);
+    assert_eq!(subset.netlist.devices.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_devices() -> Result<()> {
+    let (_info, nl, _) = parse_dspf(TEST_DSPF, &LoadOptions::default(), None)?;
+
+    assert_eq!(nl.devices.len(), 1);
+    let dev = nl.get_device("XI0/MM1")?;
+    assert_eq!(dev.model, "nch");
+    assert_eq!(dev.terminals.len(), 4);
+    assert_eq!(dev.param("W"), Some(1e-6));
+    assert_eq!(dev.param("nf"), Some(2.0));
+    assert_eq!(dev.coord(), Some((0.5, 1.1)));
+
+    // gate terminal leads to the parasitic network of net 'in'
+    assert_eq!(nl.get_terminal_net(dev, 1)?.info.name, "in");
+    assert_eq!(nl.get_net_devices("out")?.len(), 1);
+    assert_eq!(nl.get_net_devices("vss")?.len(), 1);
+
+    // continuation lines and subckt instances without parameters
+    let data = TEST_DSPF.replace(
+        "XI0/MM1 XI0/MM1#d XI0/MM1#g vss vss nch w=1u l=100n nf=2 $X=0.5 $Y=1.1",
+        "XI0/MM1 XI0/MM1#d XI0/MM1#g vss\n+ vss nch w=1u\n+ l=100n\nXI1 in out buf",
+    );
+    let (_info, nl, _) = parse_dspf(&data, &LoadOptions::default(), None)?;
+    assert_eq!(nl.devices.len(), 2);
+    assert_eq!(nl.devices[0].params.len(), 2);
+    assert_eq!(nl.get_device("XI1")?.model, "buf");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_suffixes() -> Result<()> {
+    let data = TEST_DSPF
+        .replace("*|NET in 1.5e-15", "*|NET in 1.5fF")
+        .replace("g I 2e-16", "g I .2f")
+        .replace("R2 in#1 XI0/MM1#g 20.0", "R2 in#1 XI0/MM1#g 0.02kohm")
+        .replace("C1 in#1 vss 3e-16", "C1 in#1 vss 0.3f");
+    let (_info, nl, _) = parse_dspf(&data, &LoadOptions::default(), None)?;
+
+    let net = nl.get_net("in")?;
+    assert_eq!(net.total_capacitance, 1.5e-15);
+    assert_eq!(net.resistors[1].value, 20.0);
+    assert_eq!(nl.capacitors[0].value, 3e-16);
+    let pin = &nl.all_nodes[net.subnodes[1]];
+    assert!(matches!(pin.info, NodeType::InstPin { pin_cap, .. } if pin_cap == 2e-16));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_no_params() -> Result<()> {
+    // elements without any `$` parameter (no layer info), as written for netlists read from
+    // SPEF or after a reduction
+    let data = TEST_DSPF
+        .replace(
+            "R2 in#1 XI0/MM1#g 20.0 $poly $lvl=1",
+            "R2 in#1 XI0/MM1#g 20.0",
+        )
+        .replace("C1 in#1 vss 3e-16 $lvl=2", "C1 in#1 vss 3e-16");
+    let (_info, nl, _) = parse_dspf(&data, &LoadOptions::default(), None)?;
+
+    let net = nl.get_net("in")?;
+    assert_eq!(net.resistors.len(), 2);
+    assert_eq!(net.resistors[1].layer, None);
+    assert_eq!(nl.capacitors.len(), 3);
+    assert_eq!(nl.capacitors[0].layers, LayerInfo::None);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = |data: &str| parse_dspf(data, &LoadOptions::default(), None).unwrap_err();
+
+    let e = err(&TEST_DSPF.replace("R3 out out#1", "R3 out out#2"));
+    assert_eq!(
+        e,
+        DspfError::UnknownNode {
+            node: String::from("out#2"),
+            at: Location {
+                line: 30,
+                column: 1,
+                line_text: String::from("R3 out out#2 5.0 $metal2 $lvl=3"),
+                net: Some(String::from("out")),
+            }
+        }
+    );
+
+    let e = err(&TEST_DSPF.replace("$poly", "$metal9"));
+    assert!(matches!(e, DspfError::MissingLayer { ref layer, .. } if layer == "metal9"));
+    assert_eq!(e.location().line, 22);
+
+    let e = err(&TEST_DSPF.replace("$lvl1=2 $lvl2=3", "$lvl1=2"));
+    assert!(matches!(e, DspfError::MalformedElement { .. }));
+    assert_eq!(e.location().line, 24);
+    assert_eq!(e.location().net.as_deref(), Some("in"));
+
+    let e = err(&TEST_DSPF.replace(".ENDS\n", ""));
+    assert!(matches!(e, DspfError::UnterminatedSubckt { .. }));
+
+    let e = err(&TEST_DSPF.replace("*|NET out 2e-15", "*|NET out"));
+    assert!(matches!(e, DspfError::MalformedNetBlock { .. }));
+    assert_eq!(e.location().line, 26);
+}
+
+#[test]
+fn test_parse_lenient() -> Result<()> {
+    let options = LoadOptions {
+        strict: false,
+        ..LoadOptions::default()
+    };
+    let data = TEST_DSPF
+        .replace(
+            "C1 in#1 vss",
+            "* ignored comment\nXYZ something odd\n+ continued\nC1 in#1 vss",
+        )
+        .replace("R3 out out#1", "R3 out out#2");
+    let (_info, nl, diagnostics) = parse_dspf(&data, &options, None)?;
+
+    // the unknown line and R3 are skipped, everything else is still there
+    assert_eq!(nl.capacitors.len(), 3);
+    assert_eq!(nl.get_net("in")?.resistors.len(), 2);
+    assert_eq!(nl.get_net("out")?.resistors.len(), 1);
+
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].line, 24);
+    assert_eq!(diagnostics[0].net.as_deref(), Some("in"));
+    assert_eq!(diagnostics[1].line, 33);
+    assert_eq!(diagnostics[1].reason, "unknown node 'out#2'");
+
+    // a broken net block is dropped along with its instances
+    let data = TEST_DSPF.replace("*|NET out 2e-15", "*|NET out");
+    let (_info, nl, diagnostics) = parse_dspf(&data, &options, None)?;
+    assert!(nl.get_net("out").is_err());
+    assert_eq!(nl.get_net("in")?.resistors.len(), 2);
+    // C2 refers to out#1
+    assert_eq!(nl.capacitors.len(), 1);
+    assert_eq!(diagnostics.len(), 2);
+    assert!(nl.devices.is_empty());
+
+    Ok(())
+}
+
 fn parse_dspf_info(input: &str) -> IResult<&str, DspfInfo> {
     let (tail, ((version, header), subckt, (ground_nets, layer_map))) =
         tuple((parse_header, parse_subckt, parse_ground_and_layers)).parse(input)?;
@@ -307,8 +886,10 @@ fn parse_info_strings(input: &str) -> IResult<&str, HashMap<String, String>> {
     ))
     .parse(input)?;
 
-    let info: HashMap<String, String> =
-        lines.iter().map(|l| (l.0.to_string(), l.1.to_string())).collect();
+    let info: HashMap<String, String> = lines
+        .iter()
+        .map(|l| (l.0.to_string(), l.1.to_string()))
+        .collect();
 
     Ok((tail, info))
 }
@@ -345,9 +926,9 @@ fn parse_ground_net(input: &str) -> IResult<&str, String> {
 fn parse_layer_map(input: &str) -> IResult<&str, HashMap<u8, String>> {
     let (tail, layer_pairs) = preceded(
         pair(tag("*LAYER_MAP"), line_ending),
-        many1(delimited(
+        many0(delimited(
             tag("*"),
-            pair(digit1, ws(identifier)),
+            pair(map_res(digit1, |i: &str| i.parse::<u8>()), ws(identifier)),
             line_ending,
         )),
     )(input)?;
@@ -356,7 +937,7 @@ fn parse_layer_map(input: &str) -> IResult<&str, HashMap<u8, String>> {
         tail,
         layer_pairs
             .iter()
-            .map(|(i, name)| (i.parse::<u8>().unwrap(), name.to_string()))
+            .map(|(i, name)| (*i, name.to_string()))
             .collect(),
     ))
 }
@@ -469,6 +1050,7 @@ fn parse_nodedef(input: &str) -> IResult<&str, Node> {
         info,
         coord,
         capacitors: Vec::new(),
+        devices: Vec::new(),
         of_net: 0,
     };
 
@@ -521,10 +1103,18 @@ enum ElementDef {
         value: f64,
         layers: LayerInfo,
     },
+    X {
+        name: String,
+        terminals: Vec<String>,
+        model: String,
+        params: Vec<(String, String)>,
+    },
 }
 
+/// `$name=value` parameters of an R/C line, there may be none (e.g. elements without layer
+/// info, as written by `Dspf::write` for netlists read from SPEF)
 fn parse_dollar_params(input: &str) -> IResult<&str, Vec<(String, String)>> {
-    many1(map(
+    many0(map(
         ws(preceded(
             char('$'),
             separated_pair(identifier, char('='), is_not(" \t\n")),
@@ -564,18 +1154,24 @@ fn parse_capacitor(input: &str) -> IResult<&str, ElementDef> {
         parse_dollar_params,
     ))(input)?;
 
-    let layers: LayerInfo;
-    if let Some((_, layer)) = params.iter().find(|(name, _)| name == "lvl") {
-        layers = LayerInfo::Single(layer.parse().unwrap());
-    } else if let Some((_, layer1)) = params.iter().find(|(name, _)| name == "lvl1") {
-        if let Some((_, layer2)) = params.iter().find(|(name, _)| name == "lvl2") {
-            layers = LayerInfo::Pair(layer1.parse().unwrap(), layer2.parse().unwrap());
-        } else {
-            panic!("matching $lvl2 not found in capacitor")
+    let lvl = |key: &str| {
+        params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, layer)| layer.parse::<u8>())
+    };
+    let layers = match (lvl("lvl"), lvl("lvl1"), lvl("lvl2")) {
+        (Some(Ok(layer)), None, None) => LayerInfo::Single(layer),
+        (None, Some(Ok(layer1)), Some(Ok(layer2))) => LayerInfo::Pair(layer1, layer2),
+        (None, None, None) => LayerInfo::None,
+        // invalid layer number, or $lvl1 without matching $lvl2
+        _ => {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                input,
+                ErrorKind::Verify,
+            )))
         }
-    } else {
-        layers = LayerInfo::None;
-    }
+    };
 
     Ok((
         tail,
@@ -587,9 +1183,76 @@ fn parse_capacitor(input: &str) -> IResult<&str, ElementDef> {
     ))
 }
 
-fn parse_instances(input: &str) -> IResult<&str, Vec<ElementDef>> {
-    many0(terminated(
-        alt((parse_resistor, parse_capacitor)),
-        line_ending,
-    ))(input)
+// device instance (X or M line): name, terminal nodes, model name, then `name=value` params
+fn parse_device(input: &str) -> IResult<&str, ElementDef> {
+    // anything that isn't followed by '=' is a terminal node (or the model name, if last)
+    let token = terminated(is_not(" \t\r\n=$"), not(char('=')));
+    let param = map(
+        separated_pair(
+            recognize(pair(opt(char('$')), identifier)),
+            char('='),
+            is_not(" \t\r\n"),
+        ),
+        |(name, value): (&str, &str)| (name.to_string(), value.to_string()),
+    );
+
+    let (tail, (name, mut tokens, params)) = tuple((
+        verify(ws(identifier), |s: &str| s.starts_with(['X', 'M'])),
+        many1(ws(token)),
+        many0(ws(param)),
+    ))(input)?;
+
+    // need at least one terminal and the model
+    if tokens.len() < 2 {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            ErrorKind::Verify,
+        )));
+    }
+    let model = tokens.pop().unwrap_or_default();
+
+    Ok((
+        tail,
+        ElementDef::X {
+            name: name.to_string(),
+            terminals: tokens.iter().map(|s| s.to_string()).collect(),
+            model: model.to_string(),
+            params,
+        },
+    ))
+}
+
+/// Parse all instances in a section, along with the position where each of them starts
+fn parse_instances(input: &str) -> IResult<&str, Vec<(&str, ElementDef)>> {
+    terminated(
+        many0(preceded(
+            empty_or_comment,
+            terminated(
+                consumed(alt((parse_resistor, parse_capacitor, parse_device))),
+                line_ending,
+            ),
+        )),
+        empty_or_comment,
+    )(input)
+}
+
+#[test]
+fn test_split_block() {
+    let tail = &TEST_DSPF[TEST_DSPF.find("*|NET in").unwrap()..];
+
+    let (block, rest, is_last, terminated) = split_block(tail);
+    assert!(block.starts_with("*|NET in") && block.ends_with("$lvl2=3\n\n"));
+    assert!(!is_last && terminated);
+
+    let (block, rest, is_last, terminated) = split_block(rest);
+    assert!(block.starts_with("*|NET out") && block.ends_with("$Y=1.1\n"));
+    assert!(is_last && terminated);
+    assert!(rest.starts_with(".ENDS"));
+
+    // no .ENDS, the last block goes until the end of the file
+    let unterminated = &tail[..tail.find(".ENDS").unwrap()];
+    let (_, rest, _, _) = split_block(unterminated);
+    let (block, rest, is_last, terminated) = split_block(rest);
+    assert!(block.ends_with("$Y=1.1\n") && rest.is_empty());
+    assert!(is_last && !terminated);
 }
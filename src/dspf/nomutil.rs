@@ -1,12 +1,12 @@
 #![allow(dead_code)]
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag},
+    bytes::complete::{is_not, tag, tag_no_case},
     character::complete::{
-        alphanumeric1, char, digit1, line_ending, not_line_ending, one_of, space0,
+        alpha0, alphanumeric1, char, digit0, digit1, line_ending, not_line_ending, one_of, space0,
     },
-    combinator::{map_res, not, opt, recognize},
-    error::ParseError,
+    combinator::{not, opt, recognize, value},
+    error::{ErrorKind, ParseError},
     multi::{many0, many0_count},
     sequence::{delimited, pair, preceded, terminated, tuple},
     IResult, Parser,
@@ -104,24 +104,107 @@ pub fn slash_comment(input: &str) -> IResult<&str, &str> {
     preceded(tag("//"), not_line_ending).parse(input)
 }
 
+/// Skip the current line and any continuation lines (starting with `+`) following it
+pub fn skip_statement(input: &str) -> &str {
+    let mut tail = input;
+    loop {
+        tail = match tail.find('\n') {
+            Some(n) => &tail[n + 1..],
+            None => return &tail[tail.len()..],
+        };
+        if !tail.starts_with('+') {
+            return tail;
+        }
+    }
+}
+
+#[test]
+fn test_skip_statement() {
+    assert_eq!(
+        skip_statement("R1 a b\n+ 1.0\nC1 a b 1.0\n"),
+        "C1 a b 1.0\n"
+    );
+    assert_eq!(skip_statement("R1 a b"), "");
+}
+
+#[derive(Clone, Copy)]
+enum Scale {
+    Pow10(i32),
+    Mil,
+}
+
+/// SPICE scale factor (case insensitive). Any letters following it (units) are ignored.
+fn scale_suffix(input: &str) -> IResult<&str, Option<Scale>> {
+    terminated(
+        opt(alt((
+            value(Scale::Pow10(6), tag_no_case("meg")),
+            value(Scale::Mil, tag_no_case("mil")),
+            value(Scale::Pow10(12), tag_no_case("t")),
+            value(Scale::Pow10(9), tag_no_case("g")),
+            value(Scale::Pow10(3), tag_no_case("k")),
+            value(Scale::Pow10(-3), tag_no_case("m")),
+            value(Scale::Pow10(-6), tag_no_case("u")),
+            value(Scale::Pow10(-9), tag_no_case("n")),
+            value(Scale::Pow10(-12), tag_no_case("p")),
+            value(Scale::Pow10(-15), tag_no_case("f")),
+            value(Scale::Pow10(-18), tag_no_case("a")),
+        ))),
+        alpha0,
+    )
+    .parse(input)
+}
+
+/// Number in SPICE syntax, e.g. `1.5`, `.5e-15`, `3.4p`, `2meg`, `10kOhm`
 pub fn float(input: &str) -> IResult<&str, f64> {
-    map_res(
+    let error = || nom::Err::Error(nom::error::Error::new(input, ErrorKind::Float));
+    let (tail, number) = recognize(tuple((
+        opt(one_of("+-")),
         alt((
-            recognize(tuple((
-                opt(one_of("+-")),
-                digit1,
-                opt(preceded(char('.'), digit1)),
-                one_of("eE"),
-                opt(one_of("+-")),
-                digit1,
-            ))),
-            recognize(tuple((
-                opt(one_of("+-")),
-                digit1,
-                opt(preceded(char('.'), digit1)),
-            ))),
+            recognize(pair(digit1, opt(pair(char('.'), digit0)))),
+            recognize(pair(char('.'), digit1)),
         )),
-        |out: &str| out.parse::<f64>(),
-    )
-    .parse(input)
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+    )))
+    .parse(input)?;
+    // an exponent without digits (`2e`) is not a unit
+    if tail.starts_with(['e', 'E']) {
+        return Err(error());
+    }
+    let (tail, scale) = scale_suffix(tail)?;
+
+    let parsed = match scale {
+        None => number.parse::<f64>(),
+        // shift the decimal exponent instead of multiplying, so that e.g. `1.2f` gives exactly
+        // the same value as `1.2e-15`
+        Some(Scale::Pow10(n)) => {
+            let (mantissa, exponent) = number.split_once(['e', 'E']).unwrap_or((number, "0"));
+            let exponent = exponent.parse::<i32>().unwrap_or(0) + n;
+            format!("{}e{}", mantissa, exponent).parse::<f64>()
+        }
+        Some(Scale::Mil) => number.parse::<f64>().map(|x| x * 25.4e-6),
+    };
+
+    match parsed {
+        Ok(x) => Ok((tail, x)),
+        Err(_) => Err(error()),
+    }
+}
+
+#[test]
+fn test_float() {
+    let parse = |s| float(s).unwrap();
+    assert_eq!(parse("1.5 x"), (" x", 1.5));
+    assert_eq!(parse("-2e-3)"), (")", -2e-3));
+    assert_eq!(parse(".5e-15"), ("", 0.5e-15));
+    assert_eq!(parse("+3."), ("", 3.0));
+    assert_eq!(parse("1.2f"), ("", 1.2e-15));
+    assert_eq!(parse("3.4pF"), ("", 3.4e-12));
+    assert_eq!(parse("10k $lvl=1"), (" $lvl=1", 10e3));
+    assert_eq!(parse("2MEG"), ("", 2e6));
+    assert_eq!(parse("2M"), ("", 2e-3));
+    assert_eq!(parse("0.5mil"), ("", 0.5 * 25.4e-6));
+    assert_eq!(parse("1e3ohm"), ("", 1e3));
+    assert!(float(".e5").is_err());
+    assert!(float("2e").is_err());
+    assert!(float("2E+ $lvl=1").is_err());
 }
@@ -0,0 +1,463 @@
+use std::io::Write;
+
+use color_eyre::{
+    eyre::{bail, Context, OptionExt},
+    Result,
+};
+use faer::{complex_native::c64, solvers::SpSolver, sparse::SparseColMat, Mat, Side};
+
+use super::netlist::{NetRc, Netlist};
+
+/// Integration method of the transient solver
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Integration {
+    /// First order and very stable, but damps the response for large time steps
+    BackwardEuler,
+    /// Second order, may ring slightly after the input step
+    #[default]
+    Trapezoidal,
+}
+
+/// Voltage applied to the driver node, from 0 to 1 (starting at t = 0)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stimulus {
+    Step,
+    /// Linear ramp with a 0-100% rise time
+    Ramp(f64),
+}
+
+impl Stimulus {
+    fn voltage(&self, t: f64) -> f64 {
+        match *self {
+            Stimulus::Step => 1.0,
+            Stimulus::Ramp(rise) if rise > 0.0 => (t / rise).clamp(0.0, 1.0),
+            Stimulus::Ramp(_) => 1.0,
+        }
+    }
+
+    /// Time at which the input crosses 50%
+    fn t50(&self) -> f64 {
+        match *self {
+            Stimulus::Step => 0.0,
+            Stimulus::Ramp(rise) => rise.max(0.0) / 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TranOptions {
+    pub stimulus: Stimulus,
+    pub integration: Integration,
+    /// Treatment of coupling caps to other nets, see `Netlist::net_rc` (1.0 is grounded/quiet)
+    pub miller_factor: f64,
+    /// Fixed time step, derived from the largest Elmore delay if None
+    pub time_step: Option<f64>,
+    /// End of the simulation, derived from the largest Elmore delay if None
+    pub stop_time: Option<f64>,
+}
+
+impl Default for TranOptions {
+    fn default() -> Self {
+        Self {
+            stimulus: Stimulus::Step,
+            integration: Integration::default(),
+            miller_factor: 1.0,
+            time_step: None,
+            stop_time: None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct SinkTiming {
+    pub node: String,
+    /// From the 50% point of the input to the 50% point of the sink (infinite if never reached)
+    pub delay: f64,
+    /// 10%-90% transition time (infinite if 90% is never reached)
+    pub slew: f64,
+}
+
+#[derive(Default, Debug)]
+pub struct TranResult {
+    pub net_name: String,
+    pub driver: String,
+    pub time: Vec<f64>,
+    /// Voltage at the driver
+    pub input: Vec<f64>,
+    /// Voltage at each sink (same order as `table`)
+    pub waveforms: Vec<Vec<f64>>,
+    pub table: Vec<SinkTiming>,
+}
+
+impl TranResult {
+    /// Write the waveforms with one column per node (time, driver, sinks)
+    pub fn write_csv(&self, w: &mut impl Write) -> Result<()> {
+        write!(w, "time,{}", self.driver)?;
+        for sink in &self.table {
+            write!(w, ",{}", sink.node)?;
+        }
+        writeln!(w)?;
+
+        for (i, t) in self.time.iter().enumerate() {
+            write!(w, "{:e},{}", t, self.input[i])?;
+            for waveform in &self.waveforms {
+                write!(w, ",{}", waveform[i])?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ImpedancePoint {
+    pub freq: f64,
+    pub magnitude: f64,
+    /// Phase in degrees
+    pub phase: f64,
+}
+
+#[derive(Default, Debug)]
+pub struct ImpedanceReport {
+    pub net_name: String,
+    pub node: String,
+    pub references: Vec<String>,
+    pub table: Vec<ImpedancePoint>,
+}
+
+impl ImpedanceReport {
+    /// Write the impedance curve, one frequency per line
+    pub fn write_csv(&self, w: &mut impl Write) -> Result<()> {
+        writeln!(w, "freq,magnitude,phase")?;
+        for point in &self.table {
+            writeln!(w, "{:e},{:e},{}", point.freq, point.magnitude, point.phase)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logarithmically spaced frequencies from `start` to `stop` (inclusive)
+pub fn log_sweep(start: f64, stop: f64, points_per_decade: usize) -> Vec<f64> {
+    if !(start > 0.0 && stop >= start) || points_per_decade == 0 {
+        return Vec::new();
+    }
+    let num_points = ((stop / start).log10() * points_per_decade as f64).round() as usize;
+    (0..=num_points)
+        .map(|i| start * 10f64.powf(i as f64 / points_per_decade as f64))
+        .collect()
+}
+
+/// Entries of the capacitance matrix (grounded and floating caps) for the rows of `rc`
+fn cap_triplets(rc: &NetRc) -> Vec<(usize, usize, f64)> {
+    let mut triplets: Vec<_> = (rc.ground_caps.iter().enumerate())
+        .map(|(row, &c)| (row, row, c))
+        .collect();
+    for &(a, b, c) in &rc.floating_caps {
+        if let Some(a) = a {
+            triplets.push((a, a, c));
+        }
+        if let Some(b) = b {
+            triplets.push((b, b, c));
+        }
+        if let (Some(a), Some(b)) = (a, b) {
+            triplets.push((a, b, -c));
+            triplets.push((b, a, -c));
+        }
+    }
+    triplets
+}
+
+fn mul(triplets: &[(usize, usize, f64)], v: &[f64]) -> Vec<f64> {
+    let mut res = vec![0.0; v.len()];
+    for &(row, col, value) in triplets {
+        res[row] += value * v[col];
+    }
+    res
+}
+
+/// Time of the first crossing of `level`, linearly interpolated
+fn crossing(time: &[f64], values: &[f64], level: f64) -> f64 {
+    let mut prev = (0.0, f64::NEG_INFINITY);
+    for (&t, &v) in time.iter().zip(values) {
+        if v >= level {
+            return match prev.1.is_finite() && v > prev.1 {
+                true => prev.0 + (t - prev.0) * (level - prev.1) / (v - prev.1),
+                false => t,
+            };
+        }
+        prev = (t, v);
+    }
+    f64::INFINITY
+}
+
+impl Netlist {
+    /// Transient response of the sinks (`*|I` pins) of a net to a rising input at `driver`.
+    ///
+    /// The driver is an ideal voltage source, the network is the resistors of the net with the
+    /// caps from `net_rc`. Voltages are normalized to a 0-1 swing.
+    pub fn simulate_transient(
+        &self,
+        net_name: &str,
+        driver: &str,
+        options: &TranOptions,
+    ) -> Result<TranResult> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let driver_idx = self.find_subnode(net, driver)?;
+        let rc = self.net_rc(net_name, driver_idx, options.miller_factor)?;
+        let n = rc.conductance.nodes.len();
+
+        let stop_time = options.stop_time.unwrap_or_else(|| {
+            let elmore = rc.moments(1).remove(0);
+            let max_delay = elmore.iter().fold(0.0, |max: f64, m| max.max(-m));
+            2.0 * options.stimulus.t50() + 10.0 * max_delay
+        });
+        let time_step = options.time_step.unwrap_or(stop_time / 2000.0);
+        if !(time_step > 0.0 && stop_time > 0.0) {
+            bail!(
+                "Invalid time step ({:e}) or stop time ({:e})",
+                time_step,
+                stop_time
+            );
+        }
+
+        // G (v - vd) + d/dt (C v + qd vd) = 0, with vd the driver voltage
+        let g = &rc.conductance.triplets;
+        let c = cap_triplets(&rc);
+        let gd = mul(g, &vec![1.0; n]);
+        let qd = rc.cap_times(&vec![0.0; n], 1.0);
+
+        let alpha = match options.integration {
+            Integration::BackwardEuler => 1.0 / time_step,
+            Integration::Trapezoidal => 2.0 / time_step,
+        };
+        let mut triplets = g.clone();
+        triplets.extend(c.iter().map(|&(row, col, value)| (row, col, alpha * value)));
+        let matrix = SparseColMat::try_new_from_triplets(n, n, &triplets)?;
+        let llt = matrix.sp_cholesky(Side::Lower)?;
+
+        let sinks: Vec<usize> = self.sinks(net, driver_idx).collect();
+        let sink_rows: Vec<Option<usize>> =
+            sinks.iter().map(|&idx| rc.conductance.row(idx)).collect();
+
+        let num_steps = (stop_time / time_step).ceil() as usize;
+        let mut result = TranResult {
+            net_name: net_name.to_owned(),
+            driver: driver.to_owned(),
+            time: vec![0.0],
+            input: vec![0.0],
+            waveforms: vec![vec![0.0]; sinks.len()],
+            table: Vec::new(),
+        };
+
+        let mut v = vec![0.0; n];
+        let mut vd = 0.0;
+        for step in 1..=num_steps {
+            let t = step as f64 * time_step;
+            let vd_next = options.stimulus.voltage(t);
+
+            let cv = mul(&c, &v);
+            let rhs = match options.integration {
+                Integration::BackwardEuler => Mat::from_fn(n, 1, |i, _| {
+                    alpha * (cv[i] + qd[i] * (vd - vd_next)) + gd[i] * vd_next
+                }),
+                Integration::Trapezoidal => {
+                    let gv = mul(g, &v);
+                    Mat::from_fn(n, 1, |i, _| {
+                        alpha * (cv[i] + qd[i] * (vd - vd_next)) - gv[i] + gd[i] * (vd + vd_next)
+                    })
+                }
+            };
+            let solution = llt.solve(rhs);
+            for (i, value) in v.iter_mut().enumerate() {
+                *value = solution[(i, 0)];
+            }
+            vd = vd_next;
+
+            result.time.push(t);
+            result.input.push(vd);
+            for (waveform, row) in result.waveforms.iter_mut().zip(&sink_rows) {
+                waveform.push(row.map_or(0.0, |row| v[row]));
+            }
+        }
+
+        let t50_in = options.stimulus.t50();
+        result.table = sinks
+            .iter()
+            .zip(&result.waveforms)
+            .map(|(&idx, waveform)| SinkTiming {
+                node: self.all_nodes[idx].name.clone(),
+                delay: crossing(&result.time, waveform, 0.5) - t50_in,
+                slew: crossing(&result.time, waveform, 0.9) - crossing(&result.time, waveform, 0.1),
+            })
+            .collect();
+
+        Ok(result)
+    }
+
+    /// Impedance seen at `node` with the subnodes matching `references` (see `select_subnodes`)
+    /// shorted to ground, for each of `frequencies`.
+    ///
+    /// All caps of `net_rc` are included, coupling caps to other nets are grounded. Nodes are
+    /// only part of the network if they are connected through resistors to the first reference.
+    pub fn get_impedance(
+        &self,
+        net_name: &str,
+        node: &str,
+        references: &[String],
+        frequencies: &[f64],
+    ) -> Result<ImpedanceReport> {
+        let net = self.get_net(net_name).wrap_err("Net not found.")?;
+        let node_idx = self.find_subnode(net, node)?;
+        let refs = self.select_subnodes(net, references)?;
+        if refs.contains(&node_idx) {
+            bail!("{} is one of the reference nodes", node);
+        }
+
+        let rc = self.net_rc(net_name, refs[0], 1.0)?;
+
+        // the other references are removed from the matrix as well
+        let mut new_row: Vec<Option<usize>> = (0..rc.conductance.nodes.len()).map(Some).collect();
+        for &idx in &refs[1..] {
+            if let Some(row) = rc.conductance.row(idx) {
+                new_row[row] = None;
+            }
+        }
+        let mut n = 0;
+        for row in new_row.iter_mut().flatten() {
+            *row = n;
+            n += 1;
+        }
+        let row = rc
+            .conductance
+            .row(node_idx)
+            .and_then(|row| new_row[row])
+            .ok_or_eyre(format!(
+                "{} is not connected to {}",
+                node,
+                references.join(", ")
+            ))?;
+
+        let remap = |triplets: &[(usize, usize, f64)]| -> Vec<(usize, usize, f64)> {
+            triplets
+                .iter()
+                .filter_map(|&(r, c, value)| Some((new_row[r]?, new_row[c]?, value)))
+                .collect()
+        };
+        let g = remap(&rc.conductance.triplets);
+        let c = remap(&cap_triplets(&rc));
+
+        let mut table = Vec::with_capacity(frequencies.len());
+        for &freq in frequencies {
+            let omega = 2.0 * std::f64::consts::PI * freq;
+            let triplets: Vec<(usize, usize, c64)> = (g.iter())
+                .map(|&(r, col, value)| (r, col, c64::new(value, 0.0)))
+                .chain(
+                    c.iter()
+                        .map(|&(r, col, value)| (r, col, c64::new(0.0, omega * value))),
+                )
+                .collect();
+            let matrix = SparseColMat::try_new_from_triplets(n, n, &triplets)?;
+            let lu = matrix.sp_lu()?;
+
+            let mut current = Mat::<c64>::zeros(n, 1);
+            current[(row, 0)] = c64::new(1.0, 0.0);
+            let z = lu.solve(current)[(row, 0)];
+            table.push(ImpedancePoint {
+                freq,
+                magnitude: z.norm(),
+                phase: z.im.atan2(z.re).to_degrees(),
+            });
+        }
+
+        Ok(ImpedanceReport {
+            net_name: net_name.to_owned(),
+            node: node.to_owned(),
+            references: references.to_vec(),
+            table,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dspf::nomdspf::test_netlist;
+
+    #[test]
+    fn test_transient() -> Result<()> {
+        let nl = test_netlist();
+
+        // without coupling, 'out' is a single pole: v = 1 - exp(-t/tau)
+        let tau = 5.0 * 5e-16;
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-2 * b.abs();
+        for integration in [Integration::BackwardEuler, Integration::Trapezoidal] {
+            let options = TranOptions {
+                integration,
+                miller_factor: 0.0,
+                time_step: Some(tau / 200.0),
+                stop_time: Some(5.0 * tau),
+                ..Default::default()
+            };
+            let result = nl.simulate_transient("out", "out", &options)?;
+            assert_eq!(result.table.len(), 1);
+            assert!(close(result.table[0].delay, 2f64.ln() * tau));
+            assert!(close(result.table[0].slew, 9f64.ln() * tau));
+            assert_eq!(result.time.len(), 1001);
+        }
+
+        // a slow ramp is followed with a lag of about tau
+        let options = TranOptions {
+            stimulus: Stimulus::Ramp(100.0 * tau),
+            miller_factor: 0.0,
+            ..Default::default()
+        };
+        let result = nl.simulate_transient("out", "out", &options)?;
+        assert!(close(result.table[0].delay, tau));
+        assert!(close(result.table[0].slew, 80.0 * tau));
+
+        let mut csv = Vec::new();
+        result.write_csv(&mut csv)?;
+        let csv = String::from_utf8(csv)?;
+        assert_eq!(csv.lines().next(), Some("time,out,XI0/MM1#d"));
+        assert_eq!(csv.lines().count(), result.time.len() + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_impedance() -> Result<()> {
+        let nl = test_netlist();
+
+        // 2 + 5 || (5e-16 + 1e-16), which is 4.5 - 2.5j at the pole frequency
+        let tau = 5.0 * 6e-16;
+        let pole = 1.0 / (2.0 * std::f64::consts::PI * tau);
+        let report = nl.get_impedance(
+            "out",
+            "XI0/MM1#d",
+            &[String::from("out")],
+            &[0.0, pole, 1e20],
+        )?;
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6 * b.abs();
+        assert!(close(report.table[0].magnitude, 7.0));
+        assert!(close(
+            report.table[1].magnitude,
+            (4.5f64 * 4.5 + 2.5 * 2.5).sqrt()
+        ));
+        assert!(close(
+            report.table[1].phase,
+            (-2.5f64).atan2(4.5).to_degrees()
+        ));
+        assert!(close(report.table[2].magnitude, 2.0));
+
+        // shorting both ends
+        let refs = [String::from("out"), String::from("XI0/MM1#d")];
+        let report = nl.get_impedance("out", "out#1", &refs, &[0.0])?;
+        assert!(close(report.table[0].magnitude, 5.0 * 2.0 / 7.0));
+        assert!(nl.get_impedance("out", "out", &refs, &[0.0]).is_err());
+
+        assert_eq!(log_sweep(1e3, 1e6, 2).len(), 7);
+        assert!(close(log_sweep(1e3, 1e6, 2)[6], 1e6));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,604 @@
+use super::{
+    error::{BlockLines, Diagnostic, Diagnostics, DspfError, Location},
+    netlist::{Capacitor, LayerInfo, Net, NetInfo, NetType, Netlist, Node, NodeType, Resistor},
+    nomdspf::{DspfInfo, Subckt},
+    nomutil::float,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::dspf::{LoadOptions, LoadStatus};
+
+/// True if the first statement in the file is `*SPEF`
+pub fn is_spef(data: &str) -> bool {
+    data.lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with("//"))
+        .is_some_and(|l| l.starts_with("*SPEF"))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Header,
+    NameMap,
+    Ports,
+    Conn,
+    Cap,
+    Res,
+    /// *INDUC sections and reduced nets (*R_NET...), which are not supported
+    Skip,
+}
+
+/// *CAP/*RES entry, resolved once all nets are known (coupling caps can refer to nets that come
+/// later in the file)
+struct PendingElement<'a> {
+    line: &'a str,
+    line_number: usize,
+    net: usize,
+    nodes: (String, Option<String>),
+    value: f64,
+    is_res: bool,
+}
+
+/// Value from a SPEF file, which is either a plain number or a `min:typ:max` triplet
+fn parse_value(token: &str) -> Option<f64> {
+    let token = match token.split(':').collect::<Vec<_>>()[..] {
+        [_, typ, _] => typ,
+        _ => token,
+    };
+    match float(token) {
+        Ok(("", value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Expand `*123` (and `*123:A`) references using the name map and remove escape characters.
+///
+/// The delimiter between a net or instance and its pin/subnode is replaced by `#` (the DSPF
+/// delimiter), as `:` is not valid in DSPF names and the netlist couldn't be written back.
+fn resolve_name(name_map: &HashMap<&str, &str>, delimiter: char, token: &str) -> String {
+    let mut name = String::new();
+    match token.strip_prefix('*') {
+        Some(index) => {
+            let end = index
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(index.len());
+            match name_map.get(&index[..end]) {
+                Some(mapped) => name.push_str(mapped),
+                None => name.push_str(&token[..end + 1]),
+            }
+            name.push_str(&index[end..]);
+        }
+        None => name.push_str(token),
+    }
+    let mut resolved = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => resolved.extend(chars.next()),
+            c if c == delimiter => resolved.push('#'),
+            c => resolved.push(c),
+        }
+    }
+    resolved
+}
+
+/// Node for a pin/node name, adding it to the net it belongs to if it doesn't exist yet.
+///
+/// Internal nodes are only mentioned in the *CAP/*RES sections as `net:N` (`net#N` once
+/// resolved), the owner is found by the net name before the delimiter, or `fallback_net`
+/// otherwise.
+fn find_or_add_node(
+    netlist: &mut Netlist,
+    nodes_map: &mut HashMap<String, usize>,
+    name: &str,
+    fallback_net: Option<usize>,
+) -> Option<usize> {
+    if let Some(idx) = nodes_map.get(name) {
+        return Some(*idx);
+    }
+    let owner = netlist
+        .nets_map
+        .get(name)
+        .or_else(|| netlist.nets_map.get(name.rsplit_once('#')?.0))
+        .copied()
+        .or(fallback_net)?;
+
+    let node_idx = netlist.add_node(Node {
+        name: name.to_owned(),
+        info: NodeType::Other,
+        coord: None,
+        capacitors: Vec::new(),
+        devices: Vec::new(),
+        of_net: owner,
+    });
+    netlist.all_nets[owner].subnodes.push(node_idx);
+    nodes_map.insert(name.to_owned(), node_idx);
+    Some(node_idx)
+}
+
+fn add_net(netlist: &mut Netlist, name: &str, net_type: NetType) -> usize {
+    netlist.add_net(Net {
+        info: NetInfo {
+            name: name.to_owned(),
+            net_type,
+        },
+        total_capacitance: f64::NAN,
+        subnodes: Vec::new(),
+        resistors: Vec::new(),
+    })
+}
+
+pub(super) fn parse_spef(
+    data: &str,
+    options: &LoadOptions,
+    status: Option<Arc<Mutex<LoadStatus>>>,
+) -> Result<(DspfInfo, Netlist, Vec<Diagnostic>), DspfError> {
+    let mut diagnostics = Diagnostics::new(options.strict);
+
+    if let Some(ref s) = status {
+        let mut status = s.lock().unwrap();
+        *status = LoadStatus {
+            total_bytes: data.len(),
+            ..LoadStatus::default()
+        };
+    }
+
+    let mut header: HashMap<String, String> = HashMap::new();
+    let mut name_map: HashMap<&str, &str> = HashMap::new();
+    let mut delimiter = ':';
+    let mut c_unit = 1e-12;
+    let mut r_unit = 1.0;
+
+    let mut ports: Vec<String> = Vec::new();
+    let mut ground_nets: Vec<String> = Vec::new();
+
+    let mut netlist = Netlist::default();
+    let mut nodes_map: HashMap<String, usize> = HashMap::new();
+    let mut pending: Vec<PendingElement> = Vec::new();
+
+    let mut section = Section::Header;
+    let mut current_net: Option<usize> = None;
+
+    for (line_idx, line) in data.split('\n').enumerate() {
+        let text = match line.find("//") {
+            Some(n) => &line[..n],
+            None => line,
+        };
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let net_name = current_net.map(|idx| netlist.all_nets[idx].info.name.clone());
+        let at = || BlockLines::new(line, line_idx + 1).locate(line, net_name.as_deref());
+
+        match tokens[0] {
+            "*NAME_MAP" => section = Section::NameMap,
+            "*PORTS" => section = Section::Ports,
+            "*POWER_NETS" => {}
+            "*GROUND_NETS" => {
+                ground_nets.extend(
+                    tokens[1..]
+                        .iter()
+                        .map(|t| resolve_name(&name_map, delimiter, t)),
+                );
+            }
+            "*DELIMITER" if tokens.len() == 2 => {
+                delimiter = tokens[1].chars().next().unwrap_or(':');
+            }
+            "*C_UNIT" | "*R_UNIT" => {
+                let scale = match tokens.get(2).map(|s| s.to_ascii_uppercase()).as_deref() {
+                    Some("FF") => 1e-15,
+                    Some("PF") => 1e-12,
+                    Some("OHM") => 1.0,
+                    Some("KOHM") => 1e3,
+                    _ => return Err(DspfError::MalformedHeader { at: at() }),
+                };
+                let value = tokens.get(1).and_then(|t| parse_value(t));
+                let value = value.ok_or_else(|| DspfError::MalformedHeader { at: at() })?;
+                match tokens[0] {
+                    "*C_UNIT" => c_unit = value * scale,
+                    _ => r_unit = value * scale,
+                }
+            }
+            "*D_NET" => {
+                if netlist.all_nets.is_empty() {
+                    // ground nets come first, like in the DSPF parser
+                    for name in &ground_nets {
+                        add_net(&mut netlist, name, NetType::GroundNode);
+                    }
+                }
+
+                let (name, cap) = match tokens[..] {
+                    [_, name, cap, ..] => {
+                        (resolve_name(&name_map, delimiter, name), parse_value(cap))
+                    }
+                    _ => (String::new(), None),
+                };
+                let Some(cap) = cap else {
+                    diagnostics.report(DspfError::MalformedNetBlock { at: at() })?;
+                    current_net = None;
+                    section = Section::Skip;
+                    continue;
+                };
+
+                let net_idx = match netlist.nets_map.get(&name) {
+                    Some(idx) => *idx,
+                    None => {
+                        let net_type = match ports.contains(&name) {
+                            true => NetType::SubcktPin,
+                            false => NetType::Other,
+                        };
+                        add_net(&mut netlist, &name, net_type)
+                    }
+                };
+                netlist.all_nets[net_idx].total_capacitance = cap * c_unit;
+                current_net = Some(net_idx);
+                section = Section::Skip;
+
+                if let Some(ref s) = status {
+                    let mut status = s.lock().unwrap();
+                    status.loaded_bytes = line.as_ptr() as usize - data.as_ptr() as usize;
+                    status.loaded_nets += 1;
+                }
+            }
+            "*R_NET" | "*D_PNET" | "*R_PNET" => {
+                current_net = None;
+                section = Section::Skip;
+            }
+            "*CONN" if current_net.is_some() => section = Section::Conn,
+            "*CAP" if current_net.is_some() => section = Section::Cap,
+            "*RES" if current_net.is_some() => section = Section::Res,
+            "*INDUC" => section = Section::Skip,
+            "*END" => {
+                current_net = None;
+                section = Section::Skip;
+            }
+            keyword => match section {
+                Section::Header => {
+                    if let Some(key) = keyword.strip_prefix('*') {
+                        let value = tokens[1..].join(" ");
+                        header.insert(key.to_owned(), value.trim_matches('"').to_owned());
+                    }
+                }
+                Section::NameMap => {
+                    if let (Some(index), Some(name)) = (keyword.strip_prefix('*'), tokens.get(1)) {
+                        name_map.insert(index, name);
+                    }
+                }
+                Section::Ports => ports.push(resolve_name(&name_map, delimiter, keyword)),
+                Section::Conn => {
+                    let net_idx = current_net.unwrap_or_default();
+                    let (Some(name), Some(kind)) = (tokens.get(1), keyword.strip_prefix('*'))
+                    else {
+                        diagnostics.report(DspfError::MalformedNetBlock { at: at() })?;
+                        continue;
+                    };
+                    let name = resolve_name(&name_map, delimiter, name);
+
+                    // optional fields: *C x y, *L cap, *S r1 r2, *D cell
+                    let mut coord = None;
+                    let mut pin_cap = 0.0;
+                    for (i, t) in tokens.iter().enumerate() {
+                        match *t {
+                            "*C" => {
+                                coord = tokens
+                                    .get(i + 1)
+                                    .and_then(|x| parse_value(x))
+                                    .zip(tokens.get(i + 2).and_then(|y| parse_value(y)));
+                            }
+                            "*L" => {
+                                pin_cap = tokens
+                                    .get(i + 1)
+                                    .and_then(|c| parse_value(c))
+                                    .unwrap_or(0.0)
+                                    * c_unit;
+                            }
+                            _ => {}
+                        }
+                    }
+                    let pin_type = tokens.get(2).and_then(|d| d.chars().next()).unwrap_or('B');
+
+                    let info = match kind {
+                        "P" => NodeType::SubcktPin { pin_type, pin_cap },
+                        "I" => {
+                            let (inst_name, pin_name) =
+                                name.rsplit_once('#').unwrap_or((&name, ""));
+                            NodeType::InstPin {
+                                inst_name: inst_name.to_owned(),
+                                pin_name: pin_name.to_owned(),
+                                pin_type,
+                                pin_cap,
+                            }
+                        }
+                        "N" => NodeType::Other,
+                        _ => {
+                            diagnostics.report(DspfError::MalformedNetBlock { at: at() })?;
+                            continue;
+                        }
+                    };
+
+                    if nodes_map.contains_key(&name) {
+                        continue;
+                    }
+                    let node_idx = netlist.add_node(Node {
+                        name: name.clone(),
+                        info,
+                        coord,
+                        capacitors: Vec::new(),
+                        devices: Vec::new(),
+                        of_net: net_idx,
+                    });
+                    netlist.all_nets[net_idx].subnodes.push(node_idx);
+                    nodes_map.insert(name, node_idx);
+                }
+                Section::Cap | Section::Res => {
+                    let is_res = section == Section::Res;
+                    let element = match (is_res, &tokens[..]) {
+                        (false, [_, a, value]) => Some(((a, None), value)),
+                        (_, [_, a, b, value]) => Some(((a, Some(b)), value)),
+                        _ => None,
+                    };
+                    match element.and_then(|(nodes, value)| Some((nodes, parse_value(value)?))) {
+                        Some(((a, b), value)) => pending.push(PendingElement {
+                            line,
+                            line_number: line_idx + 1,
+                            net: current_net.unwrap_or_default(),
+                            nodes: (
+                                resolve_name(&name_map, delimiter, a),
+                                b.map(|b| resolve_name(&name_map, delimiter, b)),
+                            ),
+                            value: value * if is_res { r_unit } else { c_unit },
+                            is_res,
+                        }),
+                        None => diagnostics.report(DspfError::MalformedElement { at: at() })?,
+                    }
+                }
+                Section::Skip => {}
+            },
+        }
+    }
+
+    if current_net.is_some() {
+        let net_name = current_net.map(|idx| netlist.all_nets[idx].info.name.clone());
+        diagnostics.report(DspfError::UnterminatedSubckt {
+            at: Location::new(data, &data[data.len()..], net_name.as_deref()),
+        })?;
+    }
+
+    if let Some(ref s) = status {
+        let mut status = s.lock().unwrap();
+        status.total_inst_blocks = 1;
+    }
+
+    // ground caps only have one node, they are connected to the first ground net
+    let mut ground_node: Option<usize> = None;
+
+    for el in pending {
+        let net_name = netlist.all_nets[el.net].info.name.clone();
+        let unknown = |node: &str| DspfError::UnknownNode {
+            node: node.to_owned(),
+            at: BlockLines::new(el.line, el.line_number).locate(el.line, Some(&net_name)),
+        };
+
+        let Some(a) = find_or_add_node(&mut netlist, &mut nodes_map, &el.nodes.0, Some(el.net))
+        else {
+            diagnostics.report(unknown(&el.nodes.0))?;
+            continue;
+        };
+        let b = match &el.nodes.1 {
+            // all nodes of a resistor are part of the net being defined
+            Some(name) => {
+                let fallback = if el.is_res { Some(el.net) } else { None };
+                find_or_add_node(&mut netlist, &mut nodes_map, name, fallback)
+                    .ok_or_else(|| unknown(name))
+            }
+            None => Ok(*ground_node.get_or_insert_with(|| {
+                if ground_nets.is_empty() {
+                    ground_nets.push(String::from("0"));
+                    add_net(&mut netlist, "0", NetType::GroundNode);
+                }
+                let name = ground_nets[0].clone();
+                let net_idx = netlist.nets_map[&name];
+                find_or_add_node(&mut netlist, &mut nodes_map, &name, Some(net_idx))
+                    .unwrap_or_default()
+            })),
+        };
+        let b = match b {
+            Ok(b) => b,
+            Err(err) => {
+                diagnostics.report(err)?;
+                continue;
+            }
+        };
+
+        if el.is_res {
+            let net = netlist.all_nodes[a].of_net;
+            netlist.all_nets[net].resistors.push(Resistor {
+                nodes: (a, b),
+                value: el.value,
+                layer: None,
+            });
+        } else {
+            netlist.capacitors.push(Capacitor {
+                nodes: (a, b),
+                value: el.value,
+                layers: LayerInfo::None,
+            });
+            let cap_idx = netlist.capacitors.len() - 1;
+            netlist.all_nodes[a].capacitors.push(cap_idx);
+            netlist.all_nodes[b].capacitors.push(cap_idx);
+        }
+    }
+
+    // ground nets that were declared but never got a node
+    for name in &ground_nets {
+        if let Some(&net_idx) = netlist.nets_map.get(name) {
+            find_or_add_node(&mut netlist, &mut nodes_map, name, Some(net_idx));
+        }
+    }
+
+    if let Some(ref s) = status {
+        let mut status = s.lock().unwrap();
+        status.loaded_bytes = data.len();
+        status.loaded_inst_blocks = 1;
+    }
+
+    let info = DspfInfo {
+        version: header.get("SPEF").cloned().unwrap_or_default(),
+        subckt: Subckt {
+            name: header.get("DESIGN").cloned().unwrap_or_default(),
+            ports,
+        },
+        header,
+        ground_nets,
+        // no layer info, like an empty *LAYER_MAP section
+        layer_map: Some(HashMap::new()),
+    };
+
+    Ok((info, netlist, diagnostics.list))
+}
+
+#[cfg(test)]
+const TEST_SPEF: &str = r#"*SPEF "IEEE 1481-1998"
+*DESIGN "inv"
+*DATE "Mon Jan  1 00:00:00 2024"
+*VENDOR "Vendor"
+*PROGRAM "Extractor"
+*VERSION "1.0"
+*DESIGN_FLOW "PIN_CAP NONE"
+*DIVIDER /
+*DELIMITER :
+*BUS_DELIMITER [ ]
+*T_UNIT 1 NS
+*C_UNIT 1 FF
+*R_UNIT 1 KOHM
+*L_UNIT 1 HENRY
+
+// name map
+*NAME_MAP
+*1 in
+*2 out
+*3 XI0
+*4 data\[0\]
+
+*GROUND_NETS vss
+
+*PORTS
+*1 I *C 0.0 1.0
+*2 O
+
+*D_NET *1 1.5
+*CONN
+*P *1 I *C 0.0 1.0
+*I *3:A I *C 0.5 1.0 *L 0.2 *D INV
+*CAP
+1 *1:1 0.3
+2 *1:1 *2:1 0.1
+*RES
+1 *1 *1:1 0.01
+2 *1:1 *3:A 0.02
+*END
+
+*D_NET *2 2:2.0:3
+*CONN
+*P *2 O
+*CAP
+1 *2:1 0.5
+*RES
+1 *2 *2:1 0.005
+*END
+"#;
+
+#[test]
+fn test_parse_spef() -> color_eyre::Result<()> {
+    assert!(is_spef(TEST_SPEF));
+
+    let (info, nl, _) = parse_spef(TEST_SPEF, &LoadOptions::default(), None)?;
+    assert_eq!(info.subckt.name, "inv");
+    assert_eq!(info.subckt.ports, vec!["in", "out"]);
+    assert_eq!(info.ground_nets, vec!["vss"]);
+
+    assert_eq!(nl.all_nets.len(), 3);
+    let net = nl.get_net("in")?;
+    assert_eq!(net.info.net_type, NetType::SubcktPin);
+    let approx = |a: f64, b: f64| (a - b).abs() <= 1e-9 * b.abs();
+    assert!(approx(net.total_capacitance, 1.5e-15));
+    assert_eq!(net.subnodes.len(), 3);
+    assert!(approx(net.resistors[0].value, 10.0));
+    assert!(approx(nl.get_net("out")?.total_capacitance, 2e-15));
+
+    let pin = &nl.all_nodes[net.subnodes[1]];
+    assert_eq!(pin.name, "XI0#A");
+    assert_eq!(pin.coord, Some((0.5, 1.0)));
+    assert!(
+        matches!(&pin.info, NodeType::InstPin { inst_name, pin_cap, .. }
+        if inst_name == "XI0" && approx(*pin_cap, 0.2e-15))
+    );
+
+    // existing reports work on the SPEF netlist
+    let report = nl.get_net_capacitors("in")?;
+    assert_eq!(report.table.len(), 2);
+    assert!(approx(report.table[0].cap, 0.3e-15));
+
+    let report = nl.get_path_resistance("in", &[String::from("in")], &[String::from("XI0#A")])?;
+    assert!((report.total_res - 30.0).abs() < 1e-9);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_spef_errors() {
+    let data = TEST_SPEF.replace("2 *1:1 *2:1 0.1", "2 *1:1 *9:1 0.1");
+    let err = parse_spef(&data, &LoadOptions::default(), None).unwrap_err();
+    assert!(matches!(err, DspfError::UnknownNode { ref node, .. } if node == "*9#1"));
+    assert_eq!(err.location().line, 35);
+
+    let options = LoadOptions {
+        strict: false,
+        ..LoadOptions::default()
+    };
+    let data = TEST_SPEF.replace("1 *1:1 0.3", "1 *1:1");
+    let (_, nl, diagnostics) = parse_spef(&data, &options, None).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(nl.capacitors.len(), 2);
+}
+
+#[test]
+fn test_spef_to_dspf() -> color_eyre::Result<()> {
+    use crate::dspf::Dspf;
+
+    let dir = std::env::temp_dir();
+    let file_path = dir.join(format!("test_spef_{}.spef", std::process::id()));
+    let file_path = file_path.to_str().unwrap();
+    std::fs::write(file_path, TEST_SPEF)?;
+    let spef = Dspf::load(file_path, &LoadOptions::default(), None);
+    std::fs::remove_file(file_path)?;
+    let spef = spef?;
+
+    // subnode names must be valid DSPF names to read the written file back
+    let out_path = dir.join(format!("test_spef_{}_out.dspf", std::process::id()));
+    let out_path = out_path.to_str().unwrap();
+    spef.write(out_path)?;
+    let written = Dspf::load(out_path, &LoadOptions::default(), None);
+    std::fs::remove_file(out_path)?;
+    let written = written?;
+
+    let names = |nl: &Netlist| {
+        let mut names: Vec<String> = nl.all_nodes.iter().map(|n| n.name.clone()).collect();
+        names.sort();
+        names
+    };
+    assert_eq!(names(&written.netlist), names(&spef.netlist));
+    assert!(names(&spef.netlist).contains(&String::from("in#1")));
+    assert_eq!(
+        written.netlist.capacitors.len(),
+        spef.netlist.capacitors.len()
+    );
+    let res = written.netlist.get_point_resistance("in", "in", "XI0#A")?;
+    assert!((res - 30.0).abs() < 1e-9);
+    assert_eq!(written.info.layer_map, spef.info.layer_map);
+
+    Ok(())
+}
@@ -0,0 +1,477 @@
+use std::{collections::HashMap, io::Write};
+
+use color_eyre::Result;
+use globset::{Glob, GlobSetBuilder};
+
+use super::netlist::{
+    AggrNet, Net, NetCapForAggressor, NetType, Netlist, NodeType, PiModel, ResForLayer,
+};
+
+/// Nets to include in a design-wide report
+#[derive(Debug, Clone, Default)]
+pub struct NetFilter {
+    /// Glob patterns for the net names, all nets if empty
+    pub patterns: Vec<String>,
+    /// Net types to include, all types if empty
+    pub net_types: Vec<NetType>,
+}
+
+impl NetFilter {
+    /// Indices of the nets passing the filter (unlike `Netlist::select_nets`, patterns that
+    /// don't match any net are not an error)
+    fn nets(&self, netlist: &Netlist) -> Result<Vec<usize>> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        let globs = builder.build()?;
+
+        Ok((0..netlist.all_nets.len())
+            .filter(|&idx| {
+                let info = &netlist.all_nets[idx].info;
+                (self.patterns.is_empty() || globs.is_match(&info.name))
+                    && (self.net_types.is_empty() || self.net_types.contains(&info.net_type))
+            })
+            .collect())
+    }
+}
+
+/// Column to sort a `DesignCapReport` by (numbers in decreasing order)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CapSortKey {
+    Name,
+    #[default]
+    TotalCap,
+    GroundCap,
+    CouplingCap,
+    CouplingRatio,
+    Subnodes,
+    Resistors,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetCapSummary {
+    pub net_name: String,
+    pub net_type: NetType,
+    /// Sum of all capacitors connected to the net
+    pub total_cap: f64,
+    /// Capacitance to ground nets
+    pub ground_cap: f64,
+    /// Capacitance to other (non-ground) nets
+    pub coupling_cap: f64,
+    /// `coupling_cap / total_cap`
+    pub coupling_ratio: f64,
+    /// Largest coupling caps to other nets
+    pub top_aggressors: Vec<NetCapForAggressor>,
+    pub num_subnodes: usize,
+    pub num_resistors: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct DesignCapReport {
+    pub table: Vec<NetCapSummary>,
+}
+
+impl DesignCapReport {
+    pub fn sort_by(&mut self, key: CapSortKey) {
+        let value = |net: &NetCapSummary| match key {
+            CapSortKey::Name => 0.0,
+            CapSortKey::TotalCap => net.total_cap,
+            CapSortKey::GroundCap => net.ground_cap,
+            CapSortKey::CouplingCap => net.coupling_cap,
+            CapSortKey::CouplingRatio => net.coupling_ratio,
+            CapSortKey::Subnodes => net.num_subnodes as f64,
+            CapSortKey::Resistors => net.num_resistors as f64,
+        };
+        self.table.sort_by(|a, b| {
+            value(b)
+                .total_cmp(&value(a))
+                .then_with(|| a.net_name.cmp(&b.net_name))
+        });
+    }
+}
+
+/// Column to sort a `DesignResReport` by (numbers in decreasing order)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResSortKey {
+    Name,
+    #[default]
+    TotalRes,
+    MaxRes,
+    Resistors,
+    DriverToSink,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetResSummary {
+    pub net_name: String,
+    pub net_type: NetType,
+    /// Sum of all resistor values
+    pub total_res: f64,
+    pub min_res: f64,
+    pub max_res: f64,
+    pub num_resistors: usize,
+    /// Sum of resistor values per layer, in decreasing order
+    pub per_layer: Vec<ResForLayer>,
+    /// Output instance pin, or else the subckt pin of the net
+    pub driver: Option<String>,
+    /// The pin with the largest resistance from the driver
+    pub farthest_sink: Option<String>,
+    /// Resistance from the driver to `farthest_sink` (infinite if a pin isn't connected, NaN if
+    /// there is no driver or the resistance can't be computed)
+    pub driver_to_sink_res: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct DesignResReport {
+    pub table: Vec<NetResSummary>,
+}
+
+impl DesignResReport {
+    pub fn sort_by(&mut self, key: ResSortKey) {
+        let value = |net: &NetResSummary| {
+            match key {
+                ResSortKey::Name => 0.0,
+                ResSortKey::TotalRes => net.total_res,
+                ResSortKey::MaxRes => net.max_res,
+                ResSortKey::Resistors => net.num_resistors as f64,
+                ResSortKey::DriverToSink => net.driver_to_sink_res,
+            }
+            // NaN (no resistors or no driver) last
+            .max(f64::MIN)
+        };
+        self.table.sort_by(|a, b| {
+            value(b)
+                .total_cmp(&value(a))
+                .then_with(|| a.net_name.cmp(&b.net_name))
+        });
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DesignPiReport {
+    pub miller_factor: f64,
+    /// In net order
+    pub table: Vec<PiModel>,
+}
+
+impl DesignPiReport {
+    pub fn write_csv(&self, w: &mut impl Write) -> Result<()> {
+        writeln!(w, "net,driver,total_cap,c1,r,c2")?;
+        for pi in &self.table {
+            writeln!(
+                w,
+                "{},{},{:e},{:e},{:e},{:e}",
+                pi.net_name, pi.driver, pi.total_cap, pi.c1, pi.res, pi.c2
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Netlist {
+    /// Node driving a net: the first output instance pin, or else the first subckt pin
+    fn net_driver(&self, net: &Net) -> Option<usize> {
+        let find = |f: fn(&NodeType) -> bool| {
+            (net.subnodes.iter().copied()).find(|&idx| f(&self.all_nodes[idx].info))
+        };
+        find(|info| matches!(info, NodeType::InstPin { pin_type: 'O', .. }))
+            .or_else(|| find(|info| matches!(info, NodeType::SubcktPin { .. })))
+    }
+
+    /// Pin of the net (except `driver`) with the largest resistance from `driver`
+    fn farthest_pin(&self, net: &Net, driver: usize) -> Result<Option<(usize, f64)>> {
+        let pins: Vec<usize> = (net.subnodes.iter().copied())
+            .filter(|&idx| idx != driver)
+            .filter(|&idx| {
+                matches!(
+                    self.all_nodes[idx].info,
+                    NodeType::SubcktPin { .. } | NodeType::InstPin { .. }
+                )
+            })
+            .collect();
+        if pins.is_empty() {
+            return Ok(None);
+        }
+
+        let conductance = self.net_conductance(&net.info.name, driver)?;
+        let mut res: Vec<f64> = vec![f64::INFINITY; pins.len()];
+        let connected: Vec<(usize, usize)> = (pins.iter().enumerate())
+            .filter_map(|(i, &idx)| Some((i, conductance.row(idx)?)))
+            .collect();
+        let rows: Vec<usize> = connected.iter().map(|&(_, row)| row).collect();
+        conductance.inverse_columns(&rows, |j, col| {
+            let (i, row) = connected[j];
+            res[i] = col[row];
+        });
+
+        Ok((pins.into_iter().zip(res)).max_by(|a, b| a.1.total_cmp(&b.1)))
+    }
+
+    /// Resistance summary of every net passing `filter`, sorted by `sort`.
+    ///
+    /// Nets for which the driver-to-sink resistance can't be computed (e.g. because of
+    /// resistors with a value of 0) are still reported, with a NaN resistance.
+    pub fn get_design_res_summary(
+        &self,
+        filter: &NetFilter,
+        sort: ResSortKey,
+    ) -> Result<DesignResReport> {
+        let mut table = Vec::new();
+        for idx in filter.nets(self)? {
+            let net = &self.all_nets[idx];
+
+            let mut per_layer: HashMap<Option<u8>, f64> = HashMap::new();
+            for res in &net.resistors {
+                *per_layer.entry(res.layer).or_default() += res.value;
+            }
+            let mut per_layer: Vec<ResForLayer> = per_layer
+                .into_iter()
+                .map(|(layer, res)| ResForLayer {
+                    layer_name: self.layer_name(layer),
+                    res,
+                })
+                .collect();
+            per_layer.sort_by(|a, b| {
+                b.res
+                    .total_cmp(&a.res)
+                    .then(a.layer_name.cmp(&b.layer_name))
+            });
+
+            let values = net.resistors.iter().map(|res| res.value);
+            let driver = self.net_driver(net);
+            let farthest = match driver {
+                Some(driver) => self.farthest_pin(net, driver).unwrap_or(None),
+                None => None,
+            };
+
+            table.push(NetResSummary {
+                net_name: net.info.name.clone(),
+                net_type: net.info.net_type.clone(),
+                total_res: values.clone().sum(),
+                min_res: values.clone().reduce(f64::min).unwrap_or(f64::NAN),
+                max_res: values.reduce(f64::max).unwrap_or(f64::NAN),
+                num_resistors: net.resistors.len(),
+                per_layer,
+                driver: driver.map(|idx| self.all_nodes[idx].name.clone()),
+                farthest_sink: farthest.map(|(idx, _)| self.all_nodes[idx].name.clone()),
+                driver_to_sink_res: farthest.map_or(f64::NAN, |(_, res)| res),
+            });
+        }
+
+        let mut report = DesignResReport { table };
+        report.sort_by(sort);
+        Ok(report)
+    }
+
+    /// Pi model (see `get_pi_model`) of every net passing `filter`, driven from its output
+    /// instance pin or else its subckt pin.
+    ///
+    /// Nets without a driver are skipped, nets for which the model can't be computed (e.g.
+    /// because of resistors with a value of 0) are reported with NaN values.
+    pub fn get_design_pi_models(
+        &self,
+        filter: &NetFilter,
+        miller_factor: f64,
+    ) -> Result<DesignPiReport> {
+        let mut table = Vec::new();
+        for idx in filter.nets(self)? {
+            let net = &self.all_nets[idx];
+            let Some(driver) = self.net_driver(net) else {
+                continue;
+            };
+            let driver = &self.all_nodes[driver].name;
+            let pi = self
+                .get_pi_model(&net.info.name, driver, miller_factor)
+                .unwrap_or_else(|_| PiModel {
+                    net_name: net.info.name.clone(),
+                    driver: driver.clone(),
+                    total_cap: f64::NAN,
+                    c1: f64::NAN,
+                    res: f64::NAN,
+                    c2: f64::NAN,
+                });
+            table.push(pi);
+        }
+
+        Ok(DesignPiReport {
+            miller_factor,
+            table,
+        })
+    }
+
+    /// Capacitance summary of every net passing `filter`, with the `top_n` largest aggressors
+    /// of each net, sorted by `sort`.
+    pub fn get_design_cap_summary(
+        &self,
+        filter: &NetFilter,
+        sort: CapSortKey,
+        top_n: usize,
+    ) -> Result<DesignCapReport> {
+        let nets = filter.nets(self)?;
+
+        // position in `nets`, for the nets in the report
+        let mut pos: Vec<Option<usize>> = vec![None; self.all_nets.len()];
+        for (i, &idx) in nets.iter().enumerate() {
+            pos[idx] = Some(i);
+        }
+
+        let mut table: Vec<NetCapSummary> = nets
+            .iter()
+            .map(|&idx| {
+                let net = &self.all_nets[idx];
+                NetCapSummary {
+                    net_name: net.info.name.clone(),
+                    net_type: net.info.net_type.clone(),
+                    total_cap: 0.0,
+                    ground_cap: 0.0,
+                    coupling_cap: 0.0,
+                    coupling_ratio: 0.0,
+                    top_aggressors: Vec::new(),
+                    num_subnodes: net.subnodes.len(),
+                    num_resistors: net.resistors.len(),
+                }
+            })
+            .collect();
+        let mut aggressors: Vec<HashMap<usize, f64>> = vec![HashMap::new(); nets.len()];
+
+        let is_ground = |net: usize| self.all_nets[net].info.net_type == NetType::GroundNode;
+        for cap in &self.capacitors {
+            let net_a = self.all_nodes[cap.nodes.0].of_net;
+            let net_b = self.all_nodes[cap.nodes.1].of_net;
+
+            for (net, other) in [(net_a, net_b), (net_b, net_a)] {
+                let Some(i) = pos[net] else {
+                    continue;
+                };
+                table[i].total_cap += cap.value;
+                if net == other {
+                    // caps within a net are counted once
+                    break;
+                } else if is_ground(other) {
+                    table[i].ground_cap += cap.value;
+                } else {
+                    table[i].coupling_cap += cap.value;
+                    *aggressors[i].entry(other).or_default() += cap.value;
+                }
+            }
+        }
+
+        for (summary, aggressors) in table.iter_mut().zip(aggressors) {
+            summary.coupling_ratio = match summary.total_cap > 0.0 {
+                true => summary.coupling_cap / summary.total_cap,
+                false => 0.0,
+            };
+            let mut top: Vec<NetCapForAggressor> = aggressors
+                .into_iter()
+                .map(|(idx, cap)| NetCapForAggressor {
+                    aggressor: AggrNet::Net(self.all_nets[idx].info.name.clone()),
+                    cap,
+                })
+                .collect();
+            top.sort_by(|a, b| b.cap.total_cmp(&a.cap).then(a.aggressor.cmp(&b.aggressor)));
+            top.truncate(top_n);
+            summary.top_aggressors = top;
+        }
+
+        let mut report = DesignCapReport { table };
+        report.sort_by(sort);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dspf::nomdspf::test_netlist;
+
+    #[test]
+    fn test_design_cap_summary() -> Result<()> {
+        let nl = test_netlist();
+
+        let report = nl.get_design_cap_summary(&NetFilter::default(), CapSortKey::TotalCap, 5)?;
+        let names: Vec<&str> = report.table.iter().map(|n| n.net_name.as_str()).collect();
+        assert_eq!(names, ["vss", "out", "in"]);
+
+        let net_in = &report.table[2];
+        assert!((net_in.total_cap - 4e-16).abs() < 1e-30);
+        assert_eq!(net_in.ground_cap, 3e-16);
+        assert_eq!(net_in.coupling_cap, 1e-16);
+        assert!((net_in.coupling_ratio - 0.25).abs() < 1e-12);
+        assert_eq!(net_in.top_aggressors.len(), 1);
+        assert_eq!(
+            net_in.top_aggressors[0].aggressor,
+            AggrNet::Net("out".into())
+        );
+        assert_eq!((net_in.num_subnodes, net_in.num_resistors), (3, 2));
+
+        let filter = NetFilter {
+            patterns: vec![String::from("*")],
+            net_types: vec![NetType::SubcktPin, NetType::Other],
+        };
+        let report = nl.get_design_cap_summary(&filter, CapSortKey::CouplingRatio, 0)?;
+        let names: Vec<&str> = report.table.iter().map(|n| n.net_name.as_str()).collect();
+        assert_eq!(names, ["in", "out"]);
+        assert!(report.table[0].top_aggressors.is_empty());
+
+        let filter = NetFilter {
+            patterns: vec![String::from("o*")],
+            ..Default::default()
+        };
+        let mut report = nl.get_design_cap_summary(&filter, CapSortKey::Name, 1)?;
+        assert_eq!(report.table.len(), 1);
+        report.sort_by(CapSortKey::Resistors);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_design_res_summary() -> Result<()> {
+        let nl = test_netlist();
+
+        let report = nl.get_design_res_summary(&NetFilter::default(), ResSortKey::TotalRes)?;
+        let names: Vec<&str> = report.table.iter().map(|n| n.net_name.as_str()).collect();
+        assert_eq!(names, ["in", "out", "vss"]);
+
+        let net_in = &report.table[0];
+        assert_eq!(net_in.total_res, 30.0);
+        assert_eq!((net_in.min_res, net_in.max_res), (10.0, 20.0));
+        assert_eq!(net_in.num_resistors, 2);
+        let layers: Vec<(&str, f64)> = (net_in.per_layer.iter())
+            .map(|l| (l.layer_name.as_str(), l.res))
+            .collect();
+        assert_eq!(layers, [("poly", 20.0), ("metal1", 10.0)]);
+        assert_eq!(net_in.driver.as_deref(), Some("in"));
+        assert_eq!(net_in.farthest_sink.as_deref(), Some("XI0/MM1#g"));
+        assert!((net_in.driver_to_sink_res - 30.0).abs() < 1e-9);
+
+        let vss = &report.table[2];
+        assert_eq!(vss.num_resistors, 0);
+        assert!(vss.max_res.is_nan() && vss.driver_to_sink_res.is_nan());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_design_pi_models() -> Result<()> {
+        let nl = test_netlist();
+
+        let report = nl.get_design_pi_models(&NetFilter::default(), 1.0)?;
+        let nets: Vec<(&str, &str)> = (report.table.iter())
+            .map(|pi| (pi.net_name.as_str(), pi.driver.as_str()))
+            .collect();
+        assert_eq!(nets, [("in", "in"), ("out", "out")]);
+        let out = &report.table[1];
+        assert!((out.res - 5.0).abs() < 1e-9 && (out.c2 - 6e-16).abs() < 1e-27);
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv)?;
+        let csv = String::from_utf8(csv)?;
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "net,driver,total_cap,c1,r,c2");
+        assert!(lines[2].starts_with("out,out,"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,818 @@
+use std::collections::{HashMap, HashSet};
+
+use color_eyre::{
+    eyre::{bail, OptionExt},
+    Result,
+};
+use globset::{Glob, GlobSetBuilder};
+
+use super::netlist::{
+    Capacitor, Device, LayerInfo, Net, NetType, Netlist, Node, NodeType, Resistor,
+};
+
+/// What to do with coupling capacitors to nets that are not part of a subset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExcludedCoupling {
+    /// Connect the other side to the (first) ground net, so the total net cap is preserved
+    Ground,
+    /// Remove the capacitor
+    Drop,
+}
+
+/// Coupling between two nets that is small enough to be removed by `Netlist::decouple`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CouplingThreshold {
+    /// Total coupling cap between the two nets, 0 to disable
+    pub absolute: f64,
+    /// Fraction of the total cap of each of the two nets, 0 to disable
+    pub relative: f64,
+}
+
+/// Options for `Netlist::reduce`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReduceOptions {
+    /// Subnodes with a smaller time constant (total cap over total conductance) are eliminated.
+    /// Smaller values keep more nodes and are more accurate at high frequencies.
+    pub max_time_constant: f64,
+    /// Subnodes with more resistors are kept, as eliminating them would add too many resistors
+    /// (n * (n - 1) / 2 for n neighbors)
+    pub max_degree: usize,
+}
+
+impl Default for ReduceOptions {
+    fn default() -> Self {
+        Self {
+            max_time_constant: 1e-13,
+            max_degree: 6,
+        }
+    }
+}
+
+/// Element counts before and after `Netlist::reduce`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReductionReport {
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    pub resistors_before: usize,
+    pub resistors_after: usize,
+    pub capacitors_before: usize,
+    pub capacitors_after: usize,
+}
+
+fn pair(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn copy_node(node: &Node, of_net: usize) -> Node {
+    Node {
+        name: node.name.clone(),
+        info: match &node.info {
+            NodeType::SubcktPin { pin_type, pin_cap } => NodeType::SubcktPin {
+                pin_type: *pin_type,
+                pin_cap: *pin_cap,
+            },
+            NodeType::InstPin {
+                inst_name,
+                pin_name,
+                pin_type,
+                pin_cap,
+            } => NodeType::InstPin {
+                inst_name: inst_name.clone(),
+                pin_name: pin_name.clone(),
+                pin_type: *pin_type,
+                pin_cap: *pin_cap,
+            },
+            NodeType::Ground => NodeType::Ground,
+            NodeType::Other => NodeType::Other,
+        },
+        coord: node.coord,
+        capacitors: Vec::new(),
+        devices: Vec::new(),
+        of_net,
+    }
+}
+
+impl Netlist {
+    /// Indices of the nets matching any of the glob patterns (plain net names match themselves).
+    ///
+    /// Fails if a pattern is invalid or doesn't match any net.
+    pub fn select_nets(&self, patterns: &[String]) -> Result<Vec<usize>> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        let globs = builder.build()?;
+
+        let mut matched = vec![false; patterns.len()];
+        let mut nets = Vec::new();
+        for (idx, net) in self.all_nets.iter().enumerate() {
+            let matches = globs.matches(&net.info.name);
+            for &m in &matches {
+                matched[m] = true;
+            }
+            if !matches.is_empty() {
+                nets.push(idx);
+            }
+        }
+
+        if let Some(pos) = matched.iter().position(|m| !m) {
+            bail!("No net matches '{}'", patterns[pos]);
+        }
+        Ok(nets)
+    }
+
+    /// New netlist with only the nets matching `patterns` (see `select_nets`) and the ground nets.
+    ///
+    /// Resistors and capacitors are kept if all of their nodes are part of the subset, coupling
+    /// caps to other nets are grounded or dropped depending on `coupling`. Devices are only kept
+    /// if all of their terminals are in the subset.
+    pub fn subset(&self, patterns: &[String], coupling: ExcludedCoupling) -> Result<Netlist> {
+        let mut keep = vec![false; self.all_nets.len()];
+        for idx in self.select_nets(patterns)? {
+            keep[idx] = true;
+        }
+        for (idx, net) in self.all_nets.iter().enumerate() {
+            keep[idx] |= net.info.net_type == NetType::GroundNode;
+        }
+
+        let mut netlist = Netlist {
+            layer_map: self.layer_map.clone(),
+            ..Netlist::default()
+        };
+
+        // index in the new netlist for each node of the old one
+        let mut node_map: Vec<Option<usize>> = vec![None; self.all_nodes.len()];
+
+        for (net, _) in self.all_nets.iter().zip(&keep).filter(|(_, &keep)| keep) {
+            let net_idx = netlist.add_net(Net {
+                info: net.info.clone(),
+                total_capacitance: net.total_capacitance,
+                subnodes: Vec::new(),
+                resistors: Vec::new(),
+            });
+            for &old_idx in &net.subnodes {
+                let node_idx = netlist.add_node(copy_node(&self.all_nodes[old_idx], net_idx));
+                netlist.all_nets[net_idx].subnodes.push(node_idx);
+                node_map[old_idx] = Some(node_idx);
+            }
+        }
+
+        for (net, _) in self.all_nets.iter().zip(&keep).filter(|(_, &keep)| keep) {
+            let net_idx = netlist.nets_map[&net.info.name];
+            netlist.all_nets[net_idx].resistors = net
+                .resistors
+                .iter()
+                .filter_map(|res| {
+                    Some(Resistor {
+                        nodes: (node_map[res.nodes.0]?, node_map[res.nodes.1]?),
+                        value: res.value,
+                        layer: res.layer,
+                    })
+                })
+                .collect();
+        }
+
+        let ground_node = self
+            .all_nets
+            .iter()
+            .find(|net| net.info.net_type == NetType::GroundNode)
+            .and_then(|net| net.subnodes.first())
+            .and_then(|&idx| node_map[idx]);
+
+        for cap in &self.capacitors {
+            let nodes = match (node_map[cap.nodes.0], node_map[cap.nodes.1]) {
+                (Some(a), Some(b)) => (a, b),
+                (Some(n), None) | (None, Some(n)) if coupling == ExcludedCoupling::Ground => {
+                    // ground caps of excluded nets are not coupling caps of the subset
+                    let net = &netlist.all_nets[netlist.all_nodes[n].of_net];
+                    if net.info.net_type == NetType::GroundNode {
+                        continue;
+                    }
+                    let ground =
+                        ground_node.ok_or_eyre("No ground net to connect coupling caps")?;
+                    (n, ground)
+                }
+                _ => continue,
+            };
+            netlist.capacitors.push(Capacitor {
+                nodes,
+                value: cap.value,
+                layers: cap.layers,
+            });
+            let cap_idx = netlist.capacitors.len() - 1;
+            netlist.all_nodes[nodes.0].capacitors.push(cap_idx);
+            netlist.all_nodes[nodes.1].capacitors.push(cap_idx);
+        }
+
+        for device in &self.devices {
+            let terminals: Option<Vec<usize>> =
+                device.terminals.iter().map(|&t| node_map[t]).collect();
+            if let Some(terminals) = terminals {
+                netlist.add_device(Device {
+                    name: device.name.clone(),
+                    model: device.model.clone(),
+                    terminals,
+                    params: device.params.clone(),
+                });
+            }
+        }
+
+        Ok(netlist)
+    }
+}
+
+impl Netlist {
+    /// Short resistors with a value below `threshold` (including zero and negative values) by
+    /// merging their nodes. Returns the number of resistors removed.
+    ///
+    /// A merged node is replaced by the pin (`*|P` or `*|I`) it is shorted to, if any. Two pins
+    /// are never merged, so the resistors between them are kept. Capacitors between merged
+    /// nodes are removed, the other elements are connected to the remaining node.
+    pub fn short_resistors(&mut self, threshold: f64) -> usize {
+        let n = self.all_nodes.len();
+        let mut parent: Vec<usize> = (0..n).collect();
+        let mut is_pin: Vec<bool> = (self.all_nodes.iter())
+            .map(|node| !matches!(node.info, NodeType::Other | NodeType::Ground))
+            .collect();
+        let find = |parent: &mut Vec<usize>, mut x: usize| {
+            while parent[x] != x {
+                parent[x] = parent[parent[x]];
+                x = parent[x];
+            }
+            x
+        };
+
+        for net in &self.all_nets {
+            for res in net.resistors.iter().filter(|res| res.value < threshold) {
+                let (a, b) = (
+                    find(&mut parent, res.nodes.0),
+                    find(&mut parent, res.nodes.1),
+                );
+                if a == b || (is_pin[a] && is_pin[b]) {
+                    continue;
+                }
+                let (root, other) = if is_pin[b] { (b, a) } else { (a, b) };
+                parent[other] = root;
+                is_pin[root] |= is_pin[other];
+            }
+        }
+
+        // new index of the remaining nodes, and of the node each old one is merged into
+        let mut new_idx: Vec<Option<usize>> = vec![None; n];
+        let mut count = 0;
+        for (idx, new) in new_idx.iter_mut().enumerate() {
+            if find(&mut parent, idx) == idx {
+                *new = Some(count);
+                count += 1;
+            }
+        }
+        if count == n {
+            return 0;
+        }
+        let map: Vec<usize> = (0..n)
+            .map(|idx| new_idx[find(&mut parent, idx)].expect("roots have an index"))
+            .collect();
+
+        let nodes = std::mem::take(&mut self.all_nodes);
+        self.all_nodes = (nodes.into_iter().zip(&new_idx))
+            .filter(|(_, new)| new.is_some())
+            .map(|(mut node, _)| {
+                node.capacitors.clear();
+                node.devices.clear();
+                node
+            })
+            .collect();
+
+        let mut removed = 0;
+        for net in &mut self.all_nets {
+            net.subnodes.retain(|&idx| new_idx[idx].is_some());
+            for idx in &mut net.subnodes {
+                *idx = map[*idx];
+            }
+            let num_resistors = net.resistors.len();
+            net.resistors
+                .retain(|res| map[res.nodes.0] != map[res.nodes.1]);
+            removed += num_resistors - net.resistors.len();
+            for res in &mut net.resistors {
+                res.nodes = (map[res.nodes.0], map[res.nodes.1]);
+            }
+        }
+
+        self.capacitors
+            .retain(|cap| map[cap.nodes.0] != map[cap.nodes.1]);
+        for (cap_idx, cap) in self.capacitors.iter_mut().enumerate() {
+            cap.nodes = (map[cap.nodes.0], map[cap.nodes.1]);
+            self.all_nodes[cap.nodes.0].capacitors.push(cap_idx);
+            self.all_nodes[cap.nodes.1].capacitors.push(cap_idx);
+        }
+
+        for (dev_idx, device) in self.devices.iter_mut().enumerate() {
+            for t in &mut device.terminals {
+                *t = map[*t];
+                if !self.all_nodes[*t].devices.contains(&dev_idx) {
+                    self.all_nodes[*t].devices.push(dev_idx);
+                }
+            }
+        }
+
+        removed
+    }
+}
+
+impl Netlist {
+    /// Reduced copy of the netlist, where internal subnodes (`*|S`) with a small time constant
+    /// are eliminated (TICER). Pins, ground nets and nodes connected to devices are kept.
+    ///
+    /// Eliminating a node with conductances g_i to its neighbors (G in total) adds a resistor
+    /// of conductance g_i g_j / G between each pair of neighbors, and moves its caps to the
+    /// neighbor with the largest g_i, so that the number of caps doesn't grow. This preserves the
+    /// DC resistance between the remaining nodes and the total cap of each net, except for caps
+    /// between the eliminated node and its own neighbors, and changes the Elmore delays by at
+    /// most degree * time constant for each eliminated node. New or merged elements have no
+    /// layer information.
+    pub fn reduce(&self, options: &ReduceOptions) -> (Netlist, ReductionReport) {
+        let n = self.all_nodes.len();
+        let mut conductances: HashMap<(usize, usize), (f64, Option<u8>)> = HashMap::new();
+        let mut caps: HashMap<(usize, usize), (f64, LayerInfo)> = HashMap::new();
+        let mut adj_r: Vec<HashSet<usize>> = vec![HashSet::new(); n];
+        let mut adj_c: Vec<HashSet<usize>> = vec![HashSet::new(); n];
+
+        let mut removable: Vec<bool> = (self.all_nodes.iter())
+            .map(|node| {
+                node.info == NodeType::Other
+                    && node.devices.is_empty()
+                    && self.all_nets[node.of_net].info.net_type != NetType::GroundNode
+            })
+            .collect();
+
+        fn add<L>(map: &mut HashMap<(usize, usize), (f64, L)>, key: (usize, usize), v: f64, l: L)
+        where
+            L: PartialEq + Default,
+        {
+            map.entry(key)
+                .and_modify(|(value, layer)| {
+                    *value += v;
+                    *layer = L::default();
+                })
+                .or_insert((v, l));
+        }
+
+        for net in &self.all_nets {
+            for res in &net.resistors {
+                let (a, b) = res.nodes;
+                if a == b {
+                    continue;
+                }
+                if !(res.value > 0.0 && res.value.is_finite()) {
+                    removable[a] = false;
+                    removable[b] = false;
+                }
+                add(&mut conductances, pair(a, b), 1.0 / res.value, res.layer);
+                adj_r[a].insert(b);
+                adj_r[b].insert(a);
+            }
+        }
+        for cap in &self.capacitors {
+            let (a, b) = cap.nodes;
+            if a == b {
+                continue;
+            }
+            add(&mut caps, pair(a, b), cap.value, cap.layers);
+            adj_c[a].insert(b);
+            adj_c[b].insert(a);
+        }
+
+        let time_constant = |node: usize,
+                             conductances: &HashMap<(usize, usize), (f64, Option<u8>)>,
+                             caps: &HashMap<(usize, usize), (f64, LayerInfo)>,
+                             adj_r: &[HashSet<usize>],
+                             adj_c: &[HashSet<usize>]| {
+            let g: f64 = adj_r[node]
+                .iter()
+                .map(|&i| conductances[&pair(node, i)].0)
+                .sum();
+            let c: f64 = adj_c[node].iter().map(|&i| caps[&pair(node, i)].0).sum();
+            match g > 0.0 {
+                true => c / g,
+                false => f64::INFINITY,
+            }
+        };
+
+        // quickest nodes first
+        let mut candidates: Vec<(usize, f64)> = (0..n)
+            .filter(|&node| removable[node] && !adj_r[node].is_empty())
+            .map(|node| {
+                (
+                    node,
+                    time_constant(node, &conductances, &caps, &adj_r, &adj_c),
+                )
+            })
+            .filter(|&(_, tau)| tau < options.max_time_constant)
+            .collect();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let mut eliminated = vec![false; n];
+        for (node, _) in candidates {
+            let degree = adj_r[node].len();
+            if degree == 0 || degree > options.max_degree {
+                continue;
+            }
+            // neighbors and caps change as other nodes are eliminated
+            let tau = time_constant(node, &conductances, &caps, &adj_r, &adj_c);
+            if tau >= options.max_time_constant {
+                continue;
+            }
+
+            let mut neighbors: Vec<(usize, f64)> = Vec::with_capacity(degree);
+            for i in std::mem::take(&mut adj_r[node]) {
+                adj_r[i].remove(&node);
+                let (g, _) = conductances.remove(&pair(node, i)).expect("adjacent");
+                neighbors.push((i, g));
+            }
+            neighbors.sort_by_key(|&(i, _)| i);
+            let mut node_caps: Vec<(usize, f64)> = Vec::new();
+            for k in std::mem::take(&mut adj_c[node]) {
+                adj_c[k].remove(&node);
+                let (c, _) = caps.remove(&pair(node, k)).expect("adjacent");
+                node_caps.push((k, c));
+            }
+            node_caps.sort_by_key(|&(k, _)| k);
+
+            let g_total: f64 = neighbors.iter().map(|(_, g)| g).sum();
+            for (pos, &(i, gi)) in neighbors.iter().enumerate() {
+                for &(j, gj) in &neighbors[pos + 1..] {
+                    add(&mut conductances, pair(i, j), gi * gj / g_total, None);
+                    adj_r[i].insert(j);
+                    adj_r[j].insert(i);
+                }
+            }
+            let (strongest, _) = (neighbors.iter().copied())
+                .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))
+                .expect("degree > 0");
+            for (k, c) in node_caps {
+                if k != strongest {
+                    add(&mut caps, pair(strongest, k), c, LayerInfo::None);
+                    adj_c[strongest].insert(k);
+                    adj_c[k].insert(strongest);
+                }
+            }
+            eliminated[node] = true;
+        }
+
+        // rebuild the netlist with the remaining nodes
+        let mut netlist = Netlist {
+            layer_map: self.layer_map.clone(),
+            ..Netlist::default()
+        };
+        let mut node_map: Vec<usize> = vec![usize::MAX; n];
+        for net in &self.all_nets {
+            let net_idx = netlist.add_net(Net {
+                info: net.info.clone(),
+                total_capacitance: net.total_capacitance,
+                subnodes: Vec::new(),
+                resistors: Vec::new(),
+            });
+            for &old_idx in net.subnodes.iter().filter(|&&idx| !eliminated[idx]) {
+                let node_idx = netlist.add_node(copy_node(&self.all_nodes[old_idx], net_idx));
+                netlist.all_nets[net_idx].subnodes.push(node_idx);
+                node_map[old_idx] = node_idx;
+            }
+        }
+
+        let mut conductances: Vec<_> = conductances.into_iter().collect();
+        conductances.sort_by_key(|&(key, _)| key);
+        for ((a, b), (g, layer)) in conductances {
+            let net_idx = netlist.all_nodes[node_map[a]].of_net;
+            netlist.all_nets[net_idx].resistors.push(Resistor {
+                nodes: (node_map[a], node_map[b]),
+                value: 1.0 / g,
+                layer,
+            });
+        }
+
+        let mut caps: Vec<_> = caps.into_iter().collect();
+        caps.sort_by_key(|&(key, _)| key);
+        for ((a, b), (value, layers)) in caps {
+            let nodes = (node_map[a], node_map[b]);
+            netlist.capacitors.push(Capacitor {
+                nodes,
+                value,
+                layers,
+            });
+            let cap_idx = netlist.capacitors.len() - 1;
+            netlist.all_nodes[nodes.0].capacitors.push(cap_idx);
+            netlist.all_nodes[nodes.1].capacitors.push(cap_idx);
+        }
+
+        for device in &self.devices {
+            netlist.add_device(Device {
+                name: device.name.clone(),
+                model: device.model.clone(),
+                terminals: device.terminals.iter().map(|&t| node_map[t]).collect(),
+                params: device.params.clone(),
+            });
+        }
+
+        let num_resistors = |nl: &Netlist| nl.all_nets.iter().map(|net| net.resistors.len()).sum();
+        let report = ReductionReport {
+            nodes_before: self.all_nodes.len(),
+            nodes_after: netlist.all_nodes.len(),
+            resistors_before: num_resistors(self),
+            resistors_after: num_resistors(&netlist),
+            capacitors_before: self.capacitors.len(),
+            capacitors_after: netlist.capacitors.len(),
+        };
+        (netlist, report)
+    }
+}
+
+impl Netlist {
+    /// Ground or drop the coupling caps between pairs of nets whose total coupling is below
+    /// `threshold.absolute`, or below `threshold.relative` times the total cap of both nets.
+    /// Returns the number of coupling caps that were removed.
+    ///
+    /// With `ExcludedCoupling::Ground`, each cap is replaced by a cap of the same value from
+    /// each of its nodes to the (first) ground net, so the total cap of every (non-ground) net
+    /// is preserved. It is an error if there is no ground net, and the netlist is left unchanged.
+    pub fn decouple(
+        &mut self,
+        threshold: CouplingThreshold,
+        action: ExcludedCoupling,
+    ) -> Result<usize> {
+        let net_of = |nl: &Netlist, node: usize| nl.all_nodes[node].of_net;
+        let is_ground =
+            |nl: &Netlist, net: usize| nl.all_nets[net].info.net_type == NetType::GroundNode;
+
+        let mut total_cap = vec![0.0; self.all_nets.len()];
+        let mut coupling: HashMap<(usize, usize), f64> = HashMap::new();
+        for cap in &self.capacitors {
+            let (a, b) = (net_of(self, cap.nodes.0), net_of(self, cap.nodes.1));
+            total_cap[a] += cap.value;
+            if a != b {
+                total_cap[b] += cap.value;
+                if !is_ground(self, a) && !is_ground(self, b) {
+                    *coupling.entry(pair(a, b)).or_default() += cap.value;
+                }
+            }
+        }
+        let removed: HashSet<(usize, usize)> = coupling
+            .into_iter()
+            .filter(|&((a, b), c)| {
+                c < threshold.absolute || c < threshold.relative * total_cap[a].min(total_cap[b])
+            })
+            .map(|(key, _)| key)
+            .collect();
+        if removed.is_empty() {
+            return Ok(0);
+        }
+
+        let ground_node = match action {
+            ExcludedCoupling::Ground => Some(
+                (self.all_nets.iter())
+                    .find(|net| net.info.net_type == NetType::GroundNode)
+                    .and_then(|net| net.subnodes.first().copied())
+                    .ok_or_eyre("No ground net to connect coupling caps")?,
+            ),
+            ExcludedCoupling::Drop => None,
+        };
+
+        let mut count = 0;
+        let caps = std::mem::take(&mut self.capacitors);
+        for cap in caps {
+            let key = pair(net_of(self, cap.nodes.0), net_of(self, cap.nodes.1));
+            if !removed.contains(&key) {
+                self.capacitors.push(cap);
+                continue;
+            }
+            count += 1;
+            if let Some(ground) = ground_node {
+                let (layer_a, layer_b) = match cap.layers {
+                    LayerInfo::Pair(l1, l2) => (LayerInfo::Single(l1), LayerInfo::Single(l2)),
+                    layers => (layers, layers),
+                };
+                for (node, layers) in [(cap.nodes.0, layer_a), (cap.nodes.1, layer_b)] {
+                    self.capacitors.push(Capacitor {
+                        nodes: (node, ground),
+                        value: cap.value,
+                        layers,
+                    });
+                }
+            }
+        }
+
+        for node in &mut self.all_nodes {
+            node.capacitors.clear();
+        }
+        for (cap_idx, cap) in self.capacitors.iter().enumerate() {
+            self.all_nodes[cap.nodes.0].capacitors.push(cap_idx);
+            self.all_nodes[cap.nodes.1].capacitors.push(cap_idx);
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dspf::{netlist::assert_same_netlist, nomdspf::test_netlist};
+
+    #[test]
+    fn test_subset() -> Result<()> {
+        let nl = test_netlist();
+
+        let sub = nl.subset(&[String::from("in")], ExcludedCoupling::Ground)?;
+        assert_eq!(sub.all_nets.len(), 2);
+        assert_eq!(sub.get_net("in")?.resistors.len(), 2);
+        assert_eq!(sub.capacitors.len(), 2);
+        // C2 (in#1 to out#1) now goes to ground
+        let c2 = &sub.capacitors[1];
+        assert_eq!(sub.all_nodes[c2.nodes.1].name, "vss");
+        assert_eq!(c2.value, 1e-16);
+        // XI0/MM1 is connected to 'out'
+        assert!(sub.devices.is_empty());
+
+        let sub = nl.subset(&[String::from("in")], ExcludedCoupling::Drop)?;
+        assert_eq!(sub.capacitors.len(), 1);
+
+        let sub = nl.subset(&[String::from("*")], ExcludedCoupling::Drop)?;
+        assert_eq!(sub.capacitors.len(), 3);
+        assert_eq!(sub.devices.len(), 1);
+        assert_eq!(sub.all_nodes[4].capacitors, nl.all_nodes[4].capacitors);
+
+        assert!(nl
+            .subset(
+                &[String::from("o*"), String::from("nonexistent")],
+                ExcludedCoupling::Drop
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_resistors() -> Result<()> {
+        let mut nl = test_netlist();
+        assert_eq!(nl.short_resistors(1.0), 0);
+
+        // R3 (5) and R4 (2): out#1 is merged into the pin 'out', R4 is kept between two pins
+        assert_eq!(nl.short_resistors(6.0), 1);
+        let out = nl.get_net("out")?;
+        let names: Vec<&str> = (out.subnodes.iter())
+            .map(|&idx| nl.all_nodes[idx].name.as_str())
+            .collect();
+        assert_eq!(names, ["out", "XI0/MM1#d"]);
+        assert_eq!(out.resistors.len(), 1);
+        assert_eq!(out.resistors[0].value, 2.0);
+        assert_eq!(nl.all_nodes.len(), 6);
+
+        // C3 and C2 now connect to 'out'
+        let out_idx = out.subnodes[0];
+        assert_eq!(nl.all_nodes[out_idx].capacitors, [1, 2]);
+        assert_eq!(nl.capacitors[2].nodes.1, out_idx);
+        for (idx, node) in nl.all_nodes.iter().enumerate() {
+            for &cap in &node.capacitors {
+                let nodes = nl.capacitors[cap].nodes;
+                assert!(nodes.0 == idx || nodes.1 == idx);
+            }
+        }
+        assert_eq!(nl.get_point_resistance("in", "in", "XI0/MM1#g")?, 30.0);
+        let drain = nl.devices[0].terminals[0];
+        assert_eq!(nl.all_nodes[drain].name, "XI0/MM1#d");
+        assert!(nl.all_nodes[drain].devices.contains(&0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reduce() -> Result<()> {
+        let nl = test_netlist();
+
+        // tau(in#1) = 4e-16 / 0.15, tau(out#1) = 6e-16 / 0.7
+        let options = ReduceOptions {
+            max_time_constant: 1e-15,
+            ..Default::default()
+        };
+        let (reduced, report) = nl.reduce(&options);
+        assert_eq!(report.nodes_after, 6);
+        assert_eq!(reduced.get_net("in")?.subnodes.len(), 3);
+        assert_eq!(reduced.get_net("out")?.subnodes.len(), 2);
+
+        let options = ReduceOptions::default();
+        let (reduced, report) = nl.reduce(&options);
+        assert_eq!(
+            report,
+            ReductionReport {
+                nodes_before: 7,
+                nodes_after: 5,
+                resistors_before: 4,
+                resistors_after: 2,
+                capacitors_before: 3,
+                capacitors_after: 3,
+            }
+        );
+        assert!(report.capacitors_after <= report.capacitors_before);
+        assert!(reduced.get_net("in")?.subnodes.len() == 2);
+        assert!((reduced.get_point_resistance("in", "in", "XI0/MM1#g")? - 30.0).abs() < 1e-9);
+        assert!((reduced.get_point_resistance("out", "out", "XI0/MM1#d")? - 7.0).abs() < 1e-9);
+        let total: f64 = reduced.capacitors.iter().map(|c| c.value).sum();
+        assert!((total - 9e-16).abs() < 1e-28);
+        let net_caps = |nl: &Netlist| -> Vec<(String, f64)> {
+            let mut totals = vec![0.0; nl.all_nets.len()];
+            for cap in &nl.capacitors {
+                let net_a = nl.all_nodes[cap.nodes.0].of_net;
+                let net_b = nl.all_nodes[cap.nodes.1].of_net;
+                totals[net_a] += cap.value;
+                if net_b != net_a {
+                    totals[net_b] += cap.value;
+                }
+            }
+            (nl.all_nets.iter().map(|net| net.info.name.clone()))
+                .zip(totals)
+                .collect()
+        };
+        for ((name, before), (_, after)) in net_caps(&nl).into_iter().zip(net_caps(&reduced)) {
+            assert!((before - after).abs() < 1e-28, "{}", name);
+        }
+
+        // the cap of in#1 moves to in, the Elmore delay to the gate changes by less than
+        // degree * tau(in#1)
+        let before = nl.get_elmore_delay("in", "in", 1.0)?.table[0].delay;
+        let after = reduced.get_elmore_delay("in", "in", 1.0)?.table[0].delay;
+        assert!((before - after).abs() <= 2.0 * 4e-16 / 0.15);
+
+        let drain = reduced.devices[0].terminals[0];
+        assert_eq!(reduced.all_nodes[drain].name, "XI0/MM1#d");
+
+        let options = ReduceOptions {
+            max_degree: 1,
+            ..Default::default()
+        };
+        assert_eq!(nl.reduce(&options).1.nodes_after, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decouple() -> Result<()> {
+        let totals = |nl: &Netlist| -> Result<Vec<(f64, f64)>> {
+            let report = nl.get_design_cap_summary(
+                &crate::dspf::NetFilter::default(),
+                crate::dspf::CapSortKey::Name,
+                1,
+            )?;
+            Ok(report
+                .table
+                .iter()
+                .map(|net| (net.total_cap, net.coupling_cap))
+                .collect())
+        };
+
+        // C2 (1e-16) couples in (4e-16 in total) and out (6e-16)
+        let mut nl = test_netlist();
+        let threshold = CouplingThreshold {
+            absolute: 0.0,
+            relative: 0.2,
+        };
+        assert_eq!(nl.decouple(threshold, ExcludedCoupling::Ground)?, 0);
+
+        let threshold = CouplingThreshold {
+            absolute: 0.0,
+            relative: 0.3,
+        };
+        let before = totals(&nl)?;
+        assert_eq!(nl.decouple(threshold, ExcludedCoupling::Ground)?, 1);
+        let after = totals(&nl)?;
+        assert_eq!(nl.capacitors.len(), 4);
+        // in and out (but not vss, which gets both sides of the cap)
+        for (before, after) in before[..2].iter().zip(&after) {
+            assert!((before.0 - after.0).abs() < 1e-30);
+        }
+        assert_eq!((after[0].1, after[1].1), (0.0, 0.0));
+        let in1 = nl.find_subnode(nl.get_net("in")?, "in#1")?;
+        assert_eq!(nl.all_nodes[in1].capacitors, [0, 2]);
+        assert_eq!(nl.all_nodes[nl.capacitors[2].nodes.1].name, "vss");
+        assert_eq!(nl.capacitors[2].layers, LayerInfo::Single(2));
+
+        let mut nl = test_netlist();
+        let threshold = CouplingThreshold {
+            absolute: 2e-16,
+            relative: 0.0,
+        };
+        assert_eq!(nl.decouple(threshold, ExcludedCoupling::Drop)?, 1);
+        assert_eq!(nl.capacitors.len(), 2);
+        assert!(nl.all_nodes[in1].capacitors == [0]);
+
+        // without a ground net (e.g. SPEF without *GROUND_NETS), nothing is changed
+        let no_ground = || {
+            let mut nl = test_netlist();
+            nl.all_nets[0].info.net_type = NetType::Other;
+            nl
+        };
+        let mut nl = no_ground();
+        assert!(nl.decouple(threshold, ExcludedCoupling::Ground).is_err());
+        assert_same_netlist(&nl, &no_ground());
+
+        Ok(())
+    }
+}
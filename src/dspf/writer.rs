@@ -0,0 +1,360 @@
+use std::io::Write;
+
+use color_eyre::Result;
+
+use super::{
+    netlist::{LayerInfo, NetType, Netlist, NodeType},
+    nomdspf::DspfInfo,
+};
+
+/// Header fields that the parser accepts, in the order they are written
+const HEADER_KEYS: [&str; 11] = [
+    "DESIGN",
+    "DATE",
+    "VENDOR",
+    "PROGRAM",
+    "VERSION",
+    "DIVIDER",
+    "DELIMITER",
+    "DeviceFingerDelim",
+    "BUSBIT",
+    "GLOBAL_TEMPERATURE",
+    "OPERATING_TEMPERATURE",
+];
+
+/// Shortest text that parses back to the same value, in exponent notation for very small/large
+/// values (i.e. all capacitances)
+fn fmt_value(value: f64) -> String {
+    if value == 0.0 || (1e-3..1e6).contains(&value.abs()) {
+        format!("{}", value)
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+fn fmt_coord(coord: Option<(f64, f64)>) -> String {
+    match coord {
+        Some((x, y)) => format!(" {} {}", fmt_value(x), fmt_value(y)),
+        None => String::new(),
+    }
+}
+
+/// Write `netlist` as a DSPF file, with the header, ports and ground nets from `info`.
+///
+/// Capacitors are written in the block of the net of their first node (the second one if the
+/// first is a ground node), like in the files from the extraction tools, so their block doesn't
+/// depend on the order or the subset of nets written. Devices are written after the last net
+/// block.
+pub(super) fn write_dspf(w: &mut impl Write, info: &DspfInfo, netlist: &Netlist) -> Result<()> {
+    let version = match info.version.as_str() {
+        v @ ("1.0" | "1.3" | "1.5") => v,
+        _ => "1.5",
+    };
+    writeln!(w, "*|DSPF {}", version)?;
+    for key in HEADER_KEYS {
+        if let Some(value) = info.header.get(key) {
+            match key {
+                // separator characters are written as is, e.g. `*|DIVIDER /`
+                "DIVIDER" | "DELIMITER" | "BUSBIT" => writeln!(w, "*|{} {}", key, value)?,
+                _ => writeln!(w, "*|{} \"{}\"", key, value)?,
+            }
+        }
+    }
+    writeln!(w)?;
+
+    writeln!(
+        w,
+        ".SUBCKT {} {}",
+        info.subckt.name,
+        info.subckt.ports.join(" ")
+    )?;
+    for ground in &info.ground_nets {
+        writeln!(w, "*|GROUND_NET {}", ground)?;
+    }
+    writeln!(w)?;
+
+    // the layer map is required by the parser, it is empty if there is no layer info
+    writeln!(w, "*LAYER_MAP")?;
+    let mut layers: Vec<_> = netlist.layer_map.iter().collect();
+    layers.sort();
+    for (idx, name) in layers {
+        writeln!(w, "*{} {}", idx, name)?;
+    }
+    writeln!(w)?;
+
+    // ground nets are defined by *|GROUND_NET and don't get a net block
+    let nets: Vec<usize> = (0..netlist.all_nets.len())
+        .filter(|&idx| netlist.all_nets[idx].info.net_type != NetType::GroundNode)
+        .collect();
+    let mut block_of_net = vec![None; netlist.all_nets.len()];
+    for (block, &net_idx) in nets.iter().enumerate() {
+        block_of_net[net_idx] = Some(block);
+    }
+
+    let mut caps_of_block: Vec<Vec<usize>> = vec![Vec::new(); nets.len()];
+    for (cap_idx, cap) in netlist.capacitors.iter().enumerate() {
+        let block_of_node = |node: usize| block_of_net[netlist.all_nodes[node].of_net];
+        let block = block_of_node(cap.nodes.0)
+            .or_else(|| block_of_node(cap.nodes.1))
+            .unwrap_or_default();
+        if let Some(caps) = caps_of_block.get_mut(block) {
+            caps.push(cap_idx);
+        }
+    }
+
+    let node_name = |idx: usize| netlist.all_nodes[idx].name.as_str();
+    let (mut num_r, mut num_c) = (0, 0);
+
+    for (block, &net_idx) in nets.iter().enumerate() {
+        let net = &netlist.all_nets[net_idx];
+        writeln!(
+            w,
+            "*|NET {} {}",
+            net.info.name,
+            fmt_value(net.total_capacitance)
+        )?;
+
+        for &node_idx in &net.subnodes {
+            let node = &netlist.all_nodes[node_idx];
+            let coord = fmt_coord(node.coord);
+            match &node.info {
+                NodeType::SubcktPin { pin_type, pin_cap } => writeln!(
+                    w,
+                    "*|P ({} {} {}{})",
+                    node.name,
+                    pin_type,
+                    fmt_value(*pin_cap),
+                    coord
+                )?,
+                NodeType::InstPin {
+                    inst_name,
+                    pin_name,
+                    pin_type,
+                    pin_cap,
+                } => writeln!(
+                    w,
+                    "*|I ({} {} {} {} {}{})",
+                    node.name,
+                    inst_name,
+                    pin_name,
+                    pin_type,
+                    fmt_value(*pin_cap),
+                    coord
+                )?,
+                // the parser adds the net name as a node if the block doesn't list it
+                NodeType::Other if netlist.is_implicit_node(net, node_idx) => {}
+                NodeType::Other => writeln!(w, "*|S ({}{})", node.name, coord)?,
+                NodeType::Ground => {}
+            }
+        }
+
+        for res in &net.resistors {
+            num_r += 1;
+            write!(
+                w,
+                "R{} {} {} {}",
+                num_r,
+                node_name(res.nodes.0),
+                node_name(res.nodes.1),
+                fmt_value(res.value)
+            )?;
+            if let Some(layer) = res.layer {
+                if let Some(layer_name) = netlist.layer_map.get(&layer) {
+                    write!(w, " ${} $lvl={}", layer_name, layer)?;
+                }
+            }
+            writeln!(w)?;
+        }
+
+        for &cap_idx in &caps_of_block[block] {
+            let cap = &netlist.capacitors[cap_idx];
+            num_c += 1;
+            write!(
+                w,
+                "C{} {} {} {}",
+                num_c,
+                node_name(cap.nodes.0),
+                node_name(cap.nodes.1),
+                fmt_value(cap.value)
+            )?;
+            match cap.layers {
+                LayerInfo::Single(lvl) => write!(w, " $lvl={}", lvl)?,
+                LayerInfo::Pair(lvl1, lvl2) => write!(w, " $lvl1={} $lvl2={}", lvl1, lvl2)?,
+                LayerInfo::None => {}
+            }
+            writeln!(w)?;
+        }
+
+        if block + 1 == nets.len() {
+            for device in &netlist.devices {
+                write!(w, "{}", device.name)?;
+                for &t in &device.terminals {
+                    write!(w, " {}", node_name(t))?;
+                }
+                write!(w, " {}", device.model)?;
+                for (name, value) in &device.params {
+                    write!(w, " {}={}", name, value)?;
+                }
+                writeln!(w)?;
+            }
+        }
+        writeln!(w)?;
+    }
+
+    writeln!(w, ".ENDS")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dspf::{
+        netlist::assert_same_netlist,
+        nomdspf::{with_internal_net, TEST_DSPF},
+        Dspf, ExcludedCoupling, LoadOptions, ReduceOptions,
+    };
+
+    /// (net block, node, node, value) of the capacitor lines of a written file
+    fn cap_lines(text: &str) -> Vec<(&str, &str, &str, &str)> {
+        let mut net = "";
+        let mut caps = Vec::new();
+        for line in text.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens[..] {
+                ["*|NET", name, ..] => net = name,
+                [name, a, b, value, ..] if name.starts_with('C') => caps.push((net, a, b, value)),
+                _ => {}
+            }
+        }
+        caps.sort();
+        caps
+    }
+
+    #[test]
+    fn test_fmt_value() {
+        assert_eq!(fmt_value(10.0), "10");
+        assert_eq!(fmt_value(0.5), "0.5");
+        assert_eq!(fmt_value(1.5e-15), "1.5e-15");
+        assert_eq!(fmt_value(0.0), "0");
+    }
+
+    #[test]
+    fn test_write_roundtrip() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let file_path = dir.join(format!("test_write_{}.dspf", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        std::fs::write(file_path, TEST_DSPF)?;
+        let dspf = Dspf::load(file_path, &LoadOptions::default(), None);
+        std::fs::remove_file(file_path)?;
+        let dspf = dspf?;
+
+        let out_path = dir.join(format!("test_write_{}_out.dspf", std::process::id()));
+        let out_path = out_path.to_str().unwrap();
+        dspf.write(out_path)?;
+        let written = Dspf::load(out_path, &LoadOptions::default(), None);
+        std::fs::remove_file(out_path)?;
+        let written = written?;
+
+        assert_same_netlist(&written.netlist, &dspf.netlist);
+        assert_eq!(written.info.header, dspf.info.header);
+        assert_eq!(written.info.subckt.name, dspf.info.subckt.name);
+        assert_eq!(written.info.subckt.ports, dspf.info.subckt.ports);
+        assert_eq!(written.info.ground_nets, dspf.info.ground_nets);
+        assert_eq!(written.info.layer_map, dspf.info.layer_map);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_cap_owner() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let file_path = dir.join(format!("test_cap_owner_{}.dspf", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        let load = |text: &str| {
+            std::fs::write(file_path, text)?;
+            let dspf = Dspf::load(file_path, &LoadOptions::default(), None);
+            std::fs::remove_file(file_path)?;
+            dspf
+        };
+        let dspf = load(TEST_DSPF)?;
+        let write = |netlist: &Netlist| -> Result<String> {
+            let mut out = Vec::new();
+            write_dspf(&mut out, &dspf.info, netlist)?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        // the coupling cap stays in the block of its first node, like in the source file
+        let written = write(&dspf.netlist)?;
+        let caps = cap_lines(&written);
+        assert_eq!(
+            caps,
+            [
+                ("in", "in#1", "out#1", "1e-16"),
+                ("in", "in#1", "vss", "3e-16"),
+                ("out", "out#1", "vss", "5e-16"),
+            ]
+        );
+
+        // same blocks when written again after parsing, and for a subset of the nets
+        assert_eq!(cap_lines(&write(&load(&written)?.netlist)?), caps);
+
+        let subset = (dspf.netlist).subset(&[String::from("in")], ExcludedCoupling::Drop)?;
+        assert_eq!(cap_lines(&write(&subset)?), caps[1..2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_reload() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let file_path = dir.join(format!("test_reload_{}.dspf", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        let load = |text: &str| {
+            std::fs::write(file_path, text)?;
+            let dspf = Dspf::load(file_path, &LoadOptions::default(), None);
+            std::fs::remove_file(file_path)?;
+            dspf
+        };
+
+        // one character design name, and an internal net without a node named after it
+        let text = with_internal_net(&TEST_DSPF.replace("*|DESIGN \"inv\"", "*|DESIGN \"x\""));
+        let dspf = load(&text)?;
+        let mut out = Vec::new();
+        write_dspf(&mut out, &dspf.info, &dspf.netlist)?;
+        let written = String::from_utf8(out)?;
+
+        assert!(written.contains("*|DESIGN \"x\"\n"));
+        assert!(written.contains("*|DIVIDER /\n"));
+        assert!(!written.contains("*|S (n1)"));
+
+        let reloaded = load(&written)?;
+        assert_same_netlist(&reloaded.netlist, &dspf.netlist);
+        assert_eq!(reloaded.info.header, dspf.info.header);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_reduced() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let file_path = dir.join(format!("test_reduce_{}.dspf", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        std::fs::write(file_path, TEST_DSPF)?;
+        let dspf = Dspf::load(file_path, &LoadOptions::default(), None);
+        std::fs::remove_file(file_path)?;
+        let dspf = dspf?;
+
+        let out_path = dir.join(format!("test_reduce_{}_out.dspf", std::process::id()));
+        let out_path = out_path.to_str().unwrap();
+        let report = dspf.write_reduced(out_path, &ReduceOptions::default())?;
+        let written = Dspf::load(out_path, &LoadOptions::default(), None);
+        std::fs::remove_file(out_path)?;
+        let written = written?;
+
+        let (reduced, _) = dspf.netlist.reduce(&ReduceOptions::default());
+        assert_eq!(written.netlist.all_nodes.len(), report.nodes_after);
+        assert_same_netlist(&written.netlist, &reduced);
+
+        Ok(())
+    }
+}
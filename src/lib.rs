@@ -8,7 +8,7 @@ mod tests {
     fn load_dspf() -> Result<()> {
         let file_path = "DSPF/nmos_trcp70.dspf";
 
-        let dspf = super::dspf::Dspf::load(file_path, None)?;
+        let dspf = super::dspf::Dspf::load(file_path, &Default::default(), None)?;
         dbg!(&dspf.netlist);
 
         assert_eq!(dspf.netlist.all_nets.len(), 12);